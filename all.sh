@@ -30,6 +30,7 @@ time target/release/all 9 1
 time target/release/all 9 2
 
 time target/release/all 10 1
+time target/release/all 10 2
 
 time target/release/all 11 1
 time target/release/all 11 2
@@ -68,7 +69,7 @@ time target/release/all 22 1
 time target/release/all 22 2
 
 time target/release/all 23 1
-#time target/release/all 23 2 // require z3
+time target/release/all 23 2
 
 time target/release/all 24 1
 time target/release/all 24 2
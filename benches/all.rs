@@ -10,6 +10,7 @@ use aoc::day06::chronal_coordinates;
 use aoc::day07::the_sum_of_its_part;
 use aoc::day08::memory_maneuver;
 use aoc::day09::marble_mania;
+use aoc::day10::the_stars_align;
 use aoc::day11::chronal_charge;
 use aoc::day12::subterranean_sustainability;
 use aoc::day13::mine_cart_madness;
@@ -64,6 +65,9 @@ fn criterion_benchmark(c: &mut Criterion) {
     c.bench_function("d09_a1", |b| b.iter(|| marble_mania::answer1()));
     c.bench_function("d09_a2", |b| b.iter(|| marble_mania::answer2()));
 
+    c.bench_function("d10_a1", |b| b.iter(|| the_stars_align::answer1()));
+    c.bench_function("d10_a2", |b| b.iter(|| the_stars_align::answer2()));
+
     c.bench_function("d11_a1", |b| b.iter(|| chronal_charge::answer1()));
     c.bench_function("d11_a2", |b| b.iter(|| chronal_charge::answer2()));
 
@@ -111,6 +115,9 @@ fn criterion_benchmark(c: &mut Criterion) {
     c.bench_function("d23_a1", |b| {
         b.iter(|| experimental_emergency_teleportation::answer1())
     });
+    c.bench_function("d23_a2", |b| {
+        b.iter(|| experimental_emergency_teleportation::answer2())
+    });
 
     c.bench_function("d24_a1", |b| b.iter(|| immune_system_simulator::answer1()));
     c.bench_function("d24_a2", |b| b.iter(|| immune_system_simulator::answer2()));
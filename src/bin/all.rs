@@ -56,7 +56,153 @@ fn read_argument() -> Result<(u32, u32), AocError> {
     Ok((day, problem))
 }
 
+// cargo run <day> replay [input] [delay in ms] [boost]: play back a simulation in the terminal
+fn replay() -> Result<(), AocError> {
+    let day = try_opt!(env::args().nth(1));
+    let delay = match env::args().nth(4) {
+        Some(d) => d.parse::<u64>()?,
+        None => 200,
+    };
+
+    match day.as_str() {
+        "13" => {
+            let input = env::args().nth(3);
+            mine_cart_madness::replay(input.as_deref().unwrap_or("input/input13.txt"), delay)
+        }
+        "15" => {
+            let input = env::args().nth(3);
+            beverage_bandits::replay(input.as_deref().unwrap_or("input/input15.txt"), delay)
+        }
+        "24" => {
+            let input = env::args().nth(3);
+            let boost = match env::args().nth(5) {
+                Some(b) => b.parse::<u64>()?,
+                None => 0,
+            };
+            immune_system_simulator::replay(
+                input.as_deref().unwrap_or("input/input24.txt"),
+                boost,
+                delay,
+            )
+        }
+        _ => Err(AocError::InvalidDayProblem),
+    }
+}
+
+// cargo run 24 boosts <from> <to> [input]: print the battle outcome for each boost
+fn boosts() -> Result<(), AocError> {
+    let from = try_opt!(env::args().nth(3)).parse::<u64>()?;
+    let to = try_opt!(env::args().nth(4)).parse::<u64>()?;
+    let input = env::args().nth(5);
+
+    immune_system_simulator::print_boost_table(
+        input.as_deref().unwrap_or("input/input24.txt"),
+        from..to,
+    )
+}
+
+// cargo run 13 frames <directory> [input]: write each tick of the simulation to a text file
+fn frames() -> Result<(), AocError> {
+    let dir = try_opt!(env::args().nth(3));
+    let input = env::args().nth(4);
+
+    let count =
+        mine_cart_madness::write_frames(input.as_deref().unwrap_or("input/input13.txt"), &dir)?;
+    println!("{} frames written to {}", count, dir);
+    Ok(())
+}
+
+// cargo run 17 springs [input]: print the water reached from each spring and the basins filled
+fn springs() -> Result<(), AocError> {
+    let input = env::args().nth(3);
+    reservoir_research::print_springs(input.as_deref().unwrap_or("input/input17.txt"))
+}
+
+// cargo run 20 map [input]: print the rooms and doors reached by the route
+fn map() -> Result<(), AocError> {
+    let input = env::args().nth(3);
+    a_regular_map::print_map(input.as_deref().unwrap_or("input/input20.txt"))
+}
+
+// cargo run <day> image <output.png|output.ppm> [input] [minutes]: save the state of a grid
+// simulation as an image
+fn image() -> Result<(), AocError> {
+    let day = try_opt!(env::args().nth(1));
+    let output = try_opt!(env::args().nth(3));
+    let input = env::args().nth(4);
+
+    match day.as_str() {
+        "15" => {
+            beverage_bandits::write_image(input.as_deref().unwrap_or("input/input15.txt"), &output)
+        }
+        "17" => reservoir_research::write_image(
+            input.as_deref().unwrap_or("input/input17.txt"),
+            &output,
+        ),
+        "18" => {
+            let minutes = match env::args().nth(5) {
+                Some(m) => m.parse::<usize>()?,
+                None => 10,
+            };
+            settlers_of_the_north_pole::write_image(
+                input.as_deref().unwrap_or("input/input18.txt"),
+                &output,
+                minutes,
+            )
+        }
+        _ => Err(AocError::InvalidDayProblem),
+    }
+}
+
 fn main() {
+    if env::args().nth(2).as_deref() == Some("replay") {
+        if let Err(err) = replay() {
+            println!("Error: {:?}", err);
+        }
+        return;
+    }
+
+    if env::args().nth(2).as_deref() == Some("image") {
+        if let Err(err) = image() {
+            println!("Error: {:?}", err);
+        }
+        return;
+    }
+
+    if env::args().nth(1).as_deref() == Some("24")
+        && env::args().nth(2).as_deref() == Some("boosts")
+    {
+        if let Err(err) = boosts() {
+            println!("Error: {:?}", err);
+        }
+        return;
+    }
+
+    if env::args().nth(1).as_deref() == Some("13")
+        && env::args().nth(2).as_deref() == Some("frames")
+    {
+        if let Err(err) = frames() {
+            println!("Error: {:?}", err);
+        }
+        return;
+    }
+
+    if env::args().nth(1).as_deref() == Some("17")
+        && env::args().nth(2).as_deref() == Some("springs")
+    {
+        if let Err(err) = springs() {
+            println!("Error: {:?}", err);
+        }
+        return;
+    }
+
+    if env::args().nth(1).as_deref() == Some("20") && env::args().nth(2).as_deref() == Some("map") {
+        if let Err(err) = map() {
+            println!("Error: {:?}", err);
+        }
+        return;
+    }
+
     let args = read_argument();
     if args.is_err() {
         println!("Error: {:?}", args.err().unwrap());
@@ -93,6 +239,7 @@ fn main() {
         92 => println_day!(marble_mania::answer2()),
 
         101 => println_day!(the_stars_align::answer1()),
+        102 => println_day!(the_stars_align::answer2()),
 
         111 => println_day!(chronal_charge::answer1()),
         112 => println_day!(chronal_charge::answer2()),
@@ -131,6 +278,7 @@ fn main() {
         222 => println_day!(mode_maze::answer2()),
 
         231 => println_day!(experimental_emergency_teleportation::answer1()),
+        232 => println_day!(experimental_emergency_teleportation::answer2()),
 
         241 => println_day!(immune_system_simulator::answer1()),
         242 => println_day!(immune_system_simulator::answer2()),
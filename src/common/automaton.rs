@@ -0,0 +1,474 @@
+use crate::common::error::AocError;
+
+use std::fmt;
+use std::str::FromStr;
+
+// A 2D cellular automaton described by a rule file:
+//
+//   // the first cell is the background, what lies beyond the edges of an infinite grid
+//   cells: . | #
+//   boundary: bounded            (or toroidal, or infinite)
+//   neighbourhood: moore         (the 8 cells around, or von-neumann for the 4 orthogonal ones)
+//   . if count(|) >= 3 -> |
+//   # if count(#) >= 1 and count(|) >= 1 -> #
+//   # -> .
+//
+// For each cell, the first rule matching its state and its neighbours gives its next state, a
+// cell matching no rule stays the same. `*` matches any state, conditions compare the number of
+// neighbours in a state with <, <=, ==, !=, >= or >, and are combined with `and` then `or`.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Boundary {
+    // no neighbour beyond the edges
+    Bounded,
+    // the edges wrap around
+    Toroidal,
+    // the grid grows when a cell other than the background reaches an edge
+    Infinite,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Lt,
+    Le,
+    Eq,
+    Ne,
+    Ge,
+    Gt,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Comparison {
+    cell: usize,
+    op: Op,
+    value: usize,
+}
+
+impl Comparison {
+    fn holds(&self, counts: &[usize]) -> bool {
+        let count = counts[self.cell];
+        match self.op {
+            Op::Lt => count < self.value,
+            Op::Le => count <= self.value,
+            Op::Eq => count == self.value,
+            Op::Ne => count != self.value,
+            Op::Ge => count >= self.value,
+            Op::Gt => count > self.value,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Rule {
+    // None for any state
+    from: Option<usize>,
+    // alternatives of comparisons which must all hold, always true when empty
+    condition: Vec<Vec<Comparison>>,
+    to: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Automaton {
+    cells: Vec<char>,
+    boundary: Boundary,
+    neighbours: Vec<(isize, isize)>,
+    rules: Vec<Rule>,
+}
+
+const MOORE: [(isize, isize); 8] = [
+    (-1, -1),
+    (0, -1),
+    (1, -1),
+    (-1, 0),
+    (1, 0),
+    (-1, 1),
+    (0, 1),
+    (1, 1),
+];
+const VON_NEUMANN: [(isize, isize); 4] = [(0, -1), (-1, 0), (1, 0), (0, 1)];
+
+impl FromStr for Automaton {
+    type Err = AocError;
+
+    fn from_str(s: &str) -> Result<Automaton, AocError> {
+        let mut automaton = Automaton {
+            cells: vec![],
+            boundary: Boundary::Bounded,
+            neighbours: MOORE.to_vec(),
+            rules: vec![],
+        };
+
+        for (i, line) in s.lines().enumerate() {
+            let line = line.trim();
+            let error = |msg: &str| AocError::InvalidToken(format!("line {}: {}", i + 1, msg));
+            if line.is_empty() || line.starts_with("//") {
+                continue;
+            }
+
+            if let Some(cells) = line.strip_prefix("cells:") {
+                for token in cells.split_whitespace() {
+                    let mut chars = token.chars();
+                    match (chars.next(), chars.next()) {
+                        (Some(c), None) if c != '*' && !automaton.cells.contains(&c) => {
+                            automaton.cells.push(c)
+                        }
+                        _ => return Err(error(&format!("invalid cell {:?}", token))),
+                    }
+                }
+            } else if let Some(boundary) = line.strip_prefix("boundary:") {
+                automaton.boundary = match boundary.trim() {
+                    "bounded" => Boundary::Bounded,
+                    "toroidal" => Boundary::Toroidal,
+                    "infinite" => Boundary::Infinite,
+                    b => return Err(error(&format!("unknown boundary {:?}", b))),
+                };
+            } else if let Some(neighbourhood) = line.strip_prefix("neighbourhood:") {
+                automaton.neighbours = match neighbourhood.trim() {
+                    "moore" => MOORE.to_vec(),
+                    "von-neumann" => VON_NEUMANN.to_vec(),
+                    n => return Err(error(&format!("unknown neighbourhood {:?}", n))),
+                };
+            } else {
+                let rule = automaton.parse_rule(line).map_err(|msg| error(&msg))?;
+                automaton.rules.push(rule);
+            }
+        }
+
+        if automaton.cells.is_empty() {
+            return Err(AocError::InvalidToken("no cells declared".to_string()));
+        }
+
+        Ok(automaton)
+    }
+}
+
+impl Automaton {
+    fn cell(&self, token: &str) -> Result<usize, String> {
+        let mut chars = token.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => self
+                .cells
+                .iter()
+                .position(|&cell| cell == c)
+                .ok_or_else(|| format!("undeclared cell {:?}", c)),
+            _ => Err(format!("invalid cell {:?}", token)),
+        }
+    }
+
+    // `<from> [if <condition>] -> <to>`
+    fn parse_rule(&self, line: &str) -> Result<Rule, String> {
+        if self.cells.is_empty() {
+            return Err("rule before the cells declaration".to_string());
+        }
+
+        let arrow = line.rfind("->").ok_or("missing ->")?;
+        let to = self.cell(line[arrow + 2..].trim())?;
+        let lhs = line[..arrow].trim();
+
+        let (from, condition) = match lhs.find(" if ") {
+            Some(i) => (&lhs[..i], Some(&lhs[i + 4..])),
+            None => (lhs, None),
+        };
+        let from = match from.trim() {
+            "*" => None,
+            f => Some(self.cell(f)?),
+        };
+
+        let mut alternatives = vec![];
+        if let Some(condition) = condition {
+            for alternative in condition.split(" or ") {
+                let mut comparisons = vec![];
+                for comparison in alternative.split(" and ") {
+                    comparisons.push(self.parse_comparison(comparison.trim())?);
+                }
+                alternatives.push(comparisons);
+            }
+        }
+
+        Ok(Rule {
+            from,
+            condition: alternatives,
+            to,
+        })
+    }
+
+    // `count(<cell>) <op> <value>`
+    fn parse_comparison(&self, s: &str) -> Result<Comparison, String> {
+        let invalid = || format!("invalid condition {:?}", s);
+        let rest = s.strip_prefix("count(").ok_or_else(invalid)?;
+        let close = rest.find(')').ok_or_else(invalid)?;
+        let cell = self.cell(&rest[..close])?;
+
+        let rest = rest[close + 1..].trim();
+        let (op, value) = [
+            ("<=", Op::Le),
+            (">=", Op::Ge),
+            ("==", Op::Eq),
+            ("!=", Op::Ne),
+            ("<", Op::Lt),
+            (">", Op::Gt),
+        ]
+        .iter()
+        .find_map(|(token, op)| rest.strip_prefix(token).map(|value| (*op, value)))
+        .ok_or_else(invalid)?;
+        let value = value.trim().parse::<usize>().map_err(|_| invalid())?;
+
+        Ok(Comparison { cell, op, value })
+    }
+
+    pub fn boundary(&self) -> Boundary {
+        self.boundary
+    }
+
+    pub fn cells(&self) -> &[char] {
+        &self.cells
+    }
+
+    // whether the neighbours are the 8 cells around
+    pub fn is_moore(&self) -> bool {
+        self.neighbours == MOORE
+    }
+
+    // one row per line, every row having the same length
+    pub fn parse_grid(&self, s: &str) -> Result<Grid, AocError> {
+        let mut cells = vec![];
+        let mut width = None;
+        let mut height = 0;
+        for (y, line) in s.lines().filter(|l| !l.trim().is_empty()).enumerate() {
+            let row = line
+                .trim()
+                .chars()
+                .map(|c| self.cell(&c.to_string()))
+                .collect::<Result<Vec<usize>, String>>()
+                .map_err(|msg| AocError::InvalidToken(format!("row {}: {}", y + 1, msg)))?;
+            if *width.get_or_insert(row.len()) != row.len() {
+                return Err(AocError::InvalidToken(format!(
+                    "row {}: {} cells instead of {}",
+                    y + 1,
+                    row.len(),
+                    width.unwrap()
+                )));
+            }
+            cells.extend(row.into_iter().map(|c| c as u8));
+            height += 1;
+        }
+
+        Ok(Grid {
+            alphabet: self.cells.clone(),
+            width: width.unwrap_or(0),
+            height,
+            origin: (0, 0),
+            cells,
+        })
+    }
+
+    // the next state of a cell, `counts` being its number of neighbours in each state, in the
+    // order of the cells declaration
+    pub fn next_state(&self, cell: usize, counts: &[usize]) -> usize {
+        for rule in &self.rules {
+            if rule.from.is_none_or(|from| from == cell)
+                && (rule.condition.is_empty()
+                    || rule
+                        .condition
+                        .iter()
+                        .any(|all| all.iter().all(|c| c.holds(counts))))
+            {
+                return rule.to;
+            }
+        }
+        cell
+    }
+
+    pub fn step(&self, grid: &Grid) -> Grid {
+        let grid = match self.boundary {
+            Boundary::Infinite => grid.grown(),
+            _ => grid.clone(),
+        };
+        let (width, height) = (grid.width as isize, grid.height as isize);
+
+        let mut next = grid.clone();
+        let mut counts = vec![0; self.cells.len()];
+        for y in 0..height {
+            for x in 0..width {
+                counts.iter_mut().for_each(|c| *c = 0);
+                for &(dx, dy) in &self.neighbours {
+                    let (nx, ny) = (x + dx, y + dy);
+                    let inside = nx >= 0 && ny >= 0 && nx < width && ny < height;
+                    match self.boundary {
+                        _ if inside => counts[grid.cells[(ny * width + nx) as usize] as usize] += 1,
+                        Boundary::Toroidal => {
+                            let (nx, ny) = (nx.rem_euclid(width), ny.rem_euclid(height));
+                            counts[grid.cells[(ny * width + nx) as usize] as usize] += 1
+                        }
+                        Boundary::Infinite => counts[0] += 1,
+                        Boundary::Bounded => (),
+                    }
+                }
+
+                let i = (y * width + x) as usize;
+                next.cells[i] = self.next_state(grid.cells[i] as usize, &counts) as u8;
+            }
+        }
+
+        next
+    }
+}
+
+// the state of every cell, as an index in the cells of the automaton
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Grid {
+    alphabet: Vec<char>,
+    width: usize,
+    height: usize,
+    // coordinates of the top left cell, which move when an infinite grid grows up or left
+    origin: (isize, isize),
+    cells: Vec<u8>,
+}
+
+impl Grid {
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn origin(&self) -> (isize, isize) {
+        self.origin
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> char {
+        self.alphabet[self.cells[y * self.width + x] as usize]
+    }
+
+    pub fn count(&self, c: char) -> usize {
+        match self.alphabet.iter().position(|&a| a == c) {
+            Some(i) => self
+                .cells
+                .iter()
+                .filter(|&&cell| cell as usize == i)
+                .count(),
+            None => 0,
+        }
+    }
+
+    // a copy with a row or column of background added on each edge a cell other than the
+    // background touches, so that it can spread there
+    fn grown(&self) -> Grid {
+        let w = self.width;
+        let busy = |mut cells: Box<dyn Iterator<Item = usize>>| cells.any(|i| self.cells[i] != 0);
+        let top = self.height > 0 && busy(Box::new(0..w));
+        let bottom = self.height > 0 && busy(Box::new((self.height - 1) * w..self.height * w));
+        let left = w > 0 && busy(Box::new((0..self.height).map(|y| y * w)));
+        let right = w > 0 && busy(Box::new((0..self.height).map(|y| y * w + w - 1)));
+        let (top, bottom, left, right) =
+            (top as usize, bottom as usize, left as usize, right as usize);
+
+        let width = w + left + right;
+        let height = self.height + top + bottom;
+        let mut cells = vec![0; width * height];
+        for y in 0..self.height {
+            let start = (y + top) * width + left;
+            cells[start..start + w].copy_from_slice(&self.cells[y * w..(y + 1) * w]);
+        }
+
+        Grid {
+            alphabet: self.alphabet.clone(),
+            width,
+            height,
+            origin: (self.origin.0 - left as isize, self.origin.1 - top as isize),
+            cells,
+        }
+    }
+}
+
+impl fmt::Display for Grid {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for y in 0..self.height {
+            for x in 0..self.width {
+                write!(f, "{}", self.get(x, y))?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_parse_rules() {
+    let automaton = Automaton::from_str(
+        r#"// the day 18 rules
+cells: . | #
+. if count(|) >= 3 -> |
+| if count(#) >= 3 -> #
+# if count(#) >= 1 and count(|) >= 1 -> #
+# -> ."#,
+    )
+    .unwrap();
+    assert_eq!(Boundary::Bounded, automaton.boundary());
+    assert_eq!(4, automaton.rules.len());
+    assert_eq!(Some(2), automaton.rules[2].from);
+    assert_eq!(2, automaton.rules[2].condition[0].len());
+    assert!(automaton.rules[3].condition.is_empty());
+
+    let err = |s: &str| match Automaton::from_str(s) {
+        Err(AocError::InvalidToken(msg)) => msg,
+        other => panic!("unexpected {:?}", other),
+    };
+    assert_eq!("line 2: undeclared cell 'x'", err("cells: . #\n. -> x"));
+    assert_eq!(
+        "line 2: invalid condition \"count(#) ~ 2\"",
+        err("cells: . #\n. if count(#) ~ 2 -> #")
+    );
+    assert_eq!("line 1: rule before the cells declaration", err(". -> #"));
+    assert_eq!(
+        "line 2: unknown boundary \"round\"",
+        err("cells: . #\nboundary: round")
+    );
+}
+
+#[test]
+fn test_game_of_life() {
+    let rules = r#"cells: . #
+boundary: BOUNDARY
+# if count(#) < 2 or count(#) > 3 -> .
+. if count(#) == 3 -> #"#;
+
+    // a glider on a torus comes back to its place after 4 steps per cell of the torus
+    let life = Automaton::from_str(&rules.replace("BOUNDARY", "toroidal")).unwrap();
+    let start = life
+        .parse_grid(".#....\n..#...\n###...\n......\n......\n......")
+        .unwrap();
+    let mut grid = start.clone();
+    for _ in 0..4 {
+        grid = life.step(&grid);
+    }
+    assert_eq!(
+        "......\n..#...\n...#..\n.###..\n......\n......\n",
+        grid.to_string()
+    );
+    for _ in 4..24 {
+        grid = life.step(&grid);
+    }
+    assert_eq!(start, grid);
+
+    // on an infinite grid, it keeps going down and right
+    let life = Automaton::from_str(&rules.replace("BOUNDARY", "infinite")).unwrap();
+    let mut grid = life.parse_grid(".#.\n..#\n###").unwrap();
+    for _ in 0..8 {
+        grid = life.step(&grid);
+    }
+    assert_eq!(5, grid.count('#'));
+    let cells: Vec<(isize, isize)> = (0..grid.height())
+        .flat_map(|y| (0..grid.width()).map(move |x| (x, y)))
+        .filter(|&(x, y)| grid.get(x, y) == '#')
+        .map(|(x, y)| (x as isize + grid.origin().0, y as isize + grid.origin().1))
+        .collect();
+    assert_eq!(vec![(3, 2), (4, 3), (2, 4), (3, 4), (4, 4)], cells);
+
+    // a blinker on a bounded grid
+    let life = Automaton::from_str(&rules.replace("BOUNDARY", "bounded")).unwrap();
+    let grid = life.parse_grid("...\n###\n...").unwrap();
+    assert_eq!(".#.\n.#.\n.#.\n", life.step(&grid).to_string());
+}
@@ -0,0 +1,468 @@
+use crate::common::error::AocError;
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::convert::TryFrom;
+use std::fmt::Debug;
+use std::num::Wrapping;
+use std::ops::{Add, BitAnd, BitOr, Div, Mul, Rem};
+use std::str::FromStr;
+
+// the content of a register: plain usize (overflow panics in debug, like the original puzzles)
+// or a wrapping unsigned integer to model machines with a fixed word size
+pub trait Word:
+    Copy
+    + Debug
+    + Default
+    + Eq
+    + Ord
+    + Add<Output = Self>
+    + Mul<Output = Self>
+    + BitAnd<Output = Self>
+    + BitOr<Output = Self>
+    + Div<Output = Self>
+    + Rem<Output = Self>
+{
+    fn from_usize(v: usize) -> Self;
+    fn to_usize(self) -> usize;
+
+    // the value if it fits in a word without wrapping
+    fn try_from_usize(v: usize) -> Option<Self>;
+
+    fn from_bool(b: bool) -> Self {
+        Self::from_usize(b as usize)
+    }
+}
+
+impl Word for usize {
+    fn from_usize(v: usize) -> Self {
+        v
+    }
+
+    fn to_usize(self) -> usize {
+        self
+    }
+
+    fn try_from_usize(v: usize) -> Option<Self> {
+        Some(v)
+    }
+}
+
+impl Word for Wrapping<u32> {
+    fn from_usize(v: usize) -> Self {
+        Wrapping(v as u32)
+    }
+
+    fn to_usize(self) -> usize {
+        self.0 as usize
+    }
+
+    fn try_from_usize(v: usize) -> Option<Self> {
+        u32::try_from(v).ok().map(Wrapping)
+    }
+}
+
+impl Word for Wrapping<u64> {
+    fn from_usize(v: usize) -> Self {
+        Wrapping(v as u64)
+    }
+
+    fn to_usize(self) -> usize {
+        self.0 as usize
+    }
+
+    fn try_from_usize(v: usize) -> Option<Self> {
+        u64::try_from(v).ok().map(Wrapping)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Opcode {
+    Addr,
+    Addi,
+    Mulr,
+    Muli,
+    Banr,
+    Bani,
+    Borr,
+    Bori,
+    Setr,
+    Seti,
+    Gtir,
+    Gtri,
+    Gtrr,
+    Eqir,
+    Eqri,
+    Eqrr,
+    // not part of the original instruction set, see InstructionSet::extended
+    Divr,
+    Divi,
+    Modr,
+    Modi,
+    Nop,
+}
+
+impl Opcode {
+    pub fn name(self) -> &'static str {
+        match self {
+            Opcode::Addr => "addr",
+            Opcode::Addi => "addi",
+            Opcode::Mulr => "mulr",
+            Opcode::Muli => "muli",
+            Opcode::Banr => "banr",
+            Opcode::Bani => "bani",
+            Opcode::Borr => "borr",
+            Opcode::Bori => "bori",
+            Opcode::Setr => "setr",
+            Opcode::Seti => "seti",
+            Opcode::Gtir => "gtir",
+            Opcode::Gtri => "gtri",
+            Opcode::Gtrr => "gtrr",
+            Opcode::Eqir => "eqir",
+            Opcode::Eqri => "eqri",
+            Opcode::Eqrr => "eqrr",
+            Opcode::Divr => "divr",
+            Opcode::Divi => "divi",
+            Opcode::Modr => "modr",
+            Opcode::Modi => "modi",
+            Opcode::Nop => "nop",
+        }
+    }
+
+    // which of the 3 arguments are register numbers (as opposed to immediate values or unused)
+    pub fn registers(self) -> [bool; 3] {
+        match self {
+            Opcode::Addr
+            | Opcode::Mulr
+            | Opcode::Banr
+            | Opcode::Borr
+            | Opcode::Gtrr
+            | Opcode::Eqrr
+            | Opcode::Divr
+            | Opcode::Modr => [true, true, true],
+            Opcode::Addi
+            | Opcode::Muli
+            | Opcode::Bani
+            | Opcode::Bori
+            | Opcode::Gtri
+            | Opcode::Eqri
+            | Opcode::Divi
+            | Opcode::Modi
+            | Opcode::Setr => [true, false, true],
+            Opcode::Gtir | Opcode::Eqir => [false, true, true],
+            Opcode::Seti => [false, false, true],
+            Opcode::Nop => [false, false, false],
+        }
+    }
+
+    // which of the 3 arguments are immediate values
+    pub fn immediates(self) -> [bool; 3] {
+        match self {
+            Opcode::Addi
+            | Opcode::Muli
+            | Opcode::Bani
+            | Opcode::Bori
+            | Opcode::Gtri
+            | Opcode::Eqri
+            | Opcode::Divi
+            | Opcode::Modi => [false, true, false],
+            Opcode::Gtir | Opcode::Eqir | Opcode::Seti => [true, false, false],
+            _ => [false, false, false],
+        }
+    }
+
+    // execute the instruction, return false without touching the registers when it cannot be:
+    // a division or modulo by zero
+    pub fn apply<W: Word>(self, i: &[usize], r: &mut [W]) -> bool {
+        let imm = W::from_usize;
+        let divisor = match self {
+            Opcode::Divr | Opcode::Modr => Some(r[i[1]]),
+            Opcode::Divi | Opcode::Modi => Some(imm(i[1])),
+            _ => None,
+        };
+        if divisor == Some(W::default()) {
+            return false;
+        }
+        match self {
+            Opcode::Addr => r[i[2]] = r[i[0]] + r[i[1]],
+            Opcode::Addi => r[i[2]] = r[i[0]] + imm(i[1]),
+            Opcode::Mulr => r[i[2]] = r[i[0]] * r[i[1]],
+            Opcode::Muli => r[i[2]] = r[i[0]] * imm(i[1]),
+            Opcode::Banr => r[i[2]] = r[i[0]] & r[i[1]],
+            Opcode::Bani => r[i[2]] = r[i[0]] & imm(i[1]),
+            Opcode::Borr => r[i[2]] = r[i[0]] | r[i[1]],
+            Opcode::Bori => r[i[2]] = r[i[0]] | imm(i[1]),
+            Opcode::Setr => r[i[2]] = r[i[0]],
+            Opcode::Seti => r[i[2]] = imm(i[0]),
+            Opcode::Gtir => r[i[2]] = W::from_bool(imm(i[0]) > r[i[1]]),
+            Opcode::Gtri => r[i[2]] = W::from_bool(r[i[0]] > imm(i[1])),
+            Opcode::Gtrr => r[i[2]] = W::from_bool(r[i[0]] > r[i[1]]),
+            Opcode::Eqir => r[i[2]] = W::from_bool(imm(i[0]) == r[i[1]]),
+            Opcode::Eqri => r[i[2]] = W::from_bool(r[i[0]] == imm(i[1])),
+            Opcode::Eqrr => r[i[2]] = W::from_bool(r[i[0]] == r[i[1]]),
+            Opcode::Divr => r[i[2]] = r[i[0]] / r[i[1]],
+            Opcode::Divi => r[i[2]] = r[i[0]] / imm(i[1]),
+            Opcode::Modr => r[i[2]] = r[i[0]] % r[i[1]],
+            Opcode::Modi => r[i[2]] = r[i[0]] % imm(i[1]),
+            Opcode::Nop => {}
+        }
+        true
+    }
+}
+
+// the opcodes a machine understands, parsing an instruction outside of the set is an error
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InstructionSet {
+    ops: Vec<Opcode>,
+}
+
+impl InstructionSet {
+    // the 16 instructions of the puzzles
+    pub fn standard() -> Self {
+        InstructionSet {
+            ops: vec![
+                Opcode::Addr,
+                Opcode::Addi,
+                Opcode::Mulr,
+                Opcode::Muli,
+                Opcode::Banr,
+                Opcode::Bani,
+                Opcode::Borr,
+                Opcode::Bori,
+                Opcode::Setr,
+                Opcode::Seti,
+                Opcode::Gtir,
+                Opcode::Gtri,
+                Opcode::Gtrr,
+                Opcode::Eqir,
+                Opcode::Eqri,
+                Opcode::Eqrr,
+            ],
+        }
+    }
+
+    pub fn extended() -> Self {
+        InstructionSet::standard()
+            .with(Opcode::Divr)
+            .with(Opcode::Divi)
+            .with(Opcode::Modr)
+            .with(Opcode::Modi)
+            .with(Opcode::Nop)
+    }
+
+    pub fn with(mut self, op: Opcode) -> Self {
+        if !self.ops.contains(&op) {
+            self.ops.push(op);
+        }
+        self
+    }
+
+    pub fn without(mut self, op: Opcode) -> Self {
+        self.ops.retain(|&o| o != op);
+        self
+    }
+
+    pub fn ops(&self) -> &[Opcode] {
+        &self.ops
+    }
+
+    pub fn parse_op(&self, s: &str) -> Result<Opcode, AocError> {
+        self.ops
+            .iter()
+            .find(|op| op.name() == s)
+            .cloned()
+            .ok_or_else(|| AocError::InvalidToken(s.to_string()))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Instruction {
+    pub op: Opcode,
+    pub args: [usize; 3],
+}
+
+impl Instruction {
+    pub fn parse(s: &str, set: &InstructionSet) -> Result<Self, AocError> {
+        lazy_static! {
+            static ref RE: Regex = Regex::new(r"^([a-z]+) (\d+) (\d+) (\d+)$").unwrap();
+        }
+        let c = RE
+            .captures(s.trim())
+            .ok_or_else(|| format!("cannot parse string {:?}", s))?;
+
+        let op: Opcode = set.parse_op(&c[1])?;
+        let args_1: usize = c[2].parse()?;
+        let args_2: usize = c[3].parse()?;
+        let args_3: usize = c[4].parse()?;
+
+        let args = [args_1, args_2, args_3];
+        if (op == Opcode::Divi || op == Opcode::Modi) && args_2 == 0 {
+            return Err(AocError::InvalidToken(format!(
+                "{} by zero in {:?}",
+                op.name(),
+                s
+            )));
+        }
+        Ok(Instruction { op, args })
+    }
+}
+
+impl FromStr for Instruction {
+    type Err = AocError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Instruction::parse(s, &InstructionSet::standard())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Prog {
+    pub ip: usize,
+    pub instructions: Vec<Instruction>,
+}
+
+impl Prog {
+    pub fn parse(s: &str, set: &InstructionSet) -> Result<Self, AocError> {
+        lazy_static! {
+            static ref RE_IP: Regex = Regex::new(r"^#ip (\d+)$").unwrap();
+        }
+        let mut lines = s.lines().filter(|l| !l.trim().is_empty());
+        let ip_s = lines.next().unwrap_or("");
+        let ip_a = RE_IP
+            .captures(ip_s.trim())
+            .ok_or_else(|| format!("cannot parse ip {:?}", ip_s))?;
+        let ip: usize = ip_a[1].parse()?;
+
+        let instructions = lines
+            .map(|line| Instruction::parse(line, set))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Prog { ip, instructions })
+    }
+
+    // a zeroed register file of the given size, checking that the program only refers to existing
+    // registers and that its immediate values fit in a word
+    pub fn registers<W: Word>(&self, count: usize) -> Result<Vec<W>, AocError> {
+        if self.ip >= count {
+            return Err(AocError::InvalidToken(format!(
+                "#ip {} needs more than {} registers",
+                self.ip, count
+            )));
+        }
+        for inst in &self.instructions {
+            let out_of_range = inst
+                .op
+                .registers()
+                .iter()
+                .zip(inst.args.iter())
+                .any(|(&is_reg, &arg)| is_reg && arg >= count);
+            if out_of_range {
+                return Err(AocError::InvalidToken(format!(
+                    "{:?} needs more than {} registers",
+                    inst, count
+                )));
+            }
+            let too_large = inst
+                .op
+                .immediates()
+                .iter()
+                .zip(inst.args.iter())
+                .any(|(&is_imm, &arg)| is_imm && W::try_from_usize(arg).is_none());
+            if too_large {
+                return Err(AocError::InvalidToken(format!(
+                    "{:?} has an immediate value larger than a word",
+                    inst
+                )));
+            }
+        }
+
+        Ok(vec![W::default(); count])
+    }
+
+    // execute the instruction pointed by the ip register, return false once the program halted:
+    // when the ip is out of the program, or on a division by zero
+    pub fn step<W: Word>(&self, reg: &mut [W]) -> bool {
+        match self.instructions.get(reg[self.ip].to_usize()) {
+            Some(cur) if cur.op.apply(&cur.args, reg) => {
+                reg[self.ip] = reg[self.ip] + W::from_usize(1);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn run<W: Word>(&self, reg: &mut [W]) {
+        while self.step(reg) {}
+    }
+}
+
+impl FromStr for Prog {
+    type Err = AocError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Prog::parse(s, &InstructionSet::standard())
+    }
+}
+
+#[test]
+fn test_extended_instruction_set() {
+    let s = r#"#ip 0
+seti 17 0 1
+divi 1 5 2
+modi 1 5 3
+nop 0 0 0"#;
+
+    assert!(Prog::from_str(s).is_err());
+
+    let p = Prog::parse(s, &InstructionSet::extended()).unwrap();
+    let mut reg = p.registers::<usize>(4).unwrap();
+    p.run(&mut reg);
+
+    assert_eq!(vec![4, 17, 3, 2], reg);
+    assert!(p.registers::<usize>(3).is_err());
+}
+
+#[test]
+fn test_wrapping_word() {
+    let p = Prog::from_str(
+        r#"#ip 2
+seti 4294967295 0 0
+addi 0 2 1"#,
+    )
+    .unwrap();
+
+    let mut reg = p.registers::<Wrapping<u32>>(3).unwrap();
+    p.run(&mut reg);
+    assert_eq!(Wrapping(1), reg[1]);
+
+    let mut reg = p.registers::<Wrapping<u64>>(3).unwrap();
+    p.run(&mut reg);
+    assert_eq!(Wrapping(4294967297), reg[1]);
+}
+
+#[test]
+fn test_division_by_zero() {
+    let set = InstructionSet::extended();
+    assert!(Prog::parse("#ip 0\ndivi 1 0 2", &set).is_err());
+    assert!(Prog::parse("#ip 0\nmodi 1 0 2", &set).is_err());
+
+    // the program halts on the division, leaving the registers as they were
+    let p = Prog::parse("#ip 0\nseti 7 0 1\ndivr 1 2 3\nseti 9 0 3", &set).unwrap();
+    let mut reg = p.registers::<usize>(4).unwrap();
+    p.run(&mut reg);
+    assert_eq!(vec![1, 7, 0, 0], reg);
+}
+
+#[test]
+fn test_immediate_too_large() {
+    let p = Prog::from_str("#ip 2\nseti 4294967296 0 0\nsetr 1 4294967296 1").unwrap();
+
+    assert!(p.registers::<Wrapping<u32>>(3).is_err());
+    let mut reg = p.registers::<Wrapping<u64>>(3).unwrap();
+    p.run(&mut reg);
+    assert_eq!(Wrapping(4294967296), reg[0]);
+
+    // unused arguments are not values
+    let p = Prog::from_str("#ip 2\nsetr 0 4294967296 1").unwrap();
+    assert!(p.registers::<Wrapping<u32>>(3).is_ok());
+}
@@ -13,6 +13,7 @@ pub enum AocError {
     InvalidToken(string::String),
     InvalidDayProblem,
     ComputeNotFound,
+    InfiniteLoop(string::String),
 }
 
 impl fmt::Display for AocError {
@@ -24,6 +25,7 @@ impl fmt::Display for AocError {
             AocError::InvalidToken(ref s) => write!(f, "Parse error: failed to parse string {}", s),
             AocError::InvalidDayProblem => write!(f, "Error: invalid day and/or problem"),
             AocError::ComputeNotFound => write!(f, "Computation error: no answer found"),
+            AocError::InfiniteLoop(ref s) => write!(f, "Computation error: infinite loop, {}", s),
         }
     }
 }
@@ -37,6 +39,7 @@ impl error::Error for AocError {
             AocError::InvalidToken(ref _s) => "Parse error: failed to parse string",
             AocError::InvalidDayProblem => "Error: invalid day and/or problem",
             AocError::ComputeNotFound => "Computation error: no answer found",
+            AocError::InfiniteLoop(ref _s) => "Computation error: infinite loop",
         }
     }
 
@@ -48,6 +51,7 @@ impl error::Error for AocError {
             AocError::InvalidToken(ref _s) => None,
             AocError::InvalidDayProblem => None,
             AocError::ComputeNotFound => None,
+            AocError::InfiniteLoop(ref _s) => None,
         }
     }
 }
@@ -0,0 +1,207 @@
+use crate::common::error::AocError;
+
+use std::path::Path;
+
+pub type Rgb = [u8; 3];
+
+// an RGB image, to look at the states of the grid simulations
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Image {
+    width: usize,
+    height: usize,
+    pixels: Vec<Rgb>,
+}
+
+impl Image {
+    pub fn new(width: usize, height: usize) -> Image {
+        Image {
+            width,
+            height,
+            pixels: vec![[0, 0, 0]; width * height],
+        }
+    }
+
+    // one pixel per cell of the grid, the rows possibly being of different lengths
+    pub fn from_grid<T>(grid: &[Vec<T>], colour: impl Fn(&T) -> Rgb) -> Image {
+        let width = grid.iter().map(|row| row.len()).max().unwrap_or(0);
+        let mut image = Image::new(width, grid.len());
+        for (y, row) in grid.iter().enumerate() {
+            for (x, cell) in row.iter().enumerate() {
+                image.set(x, y, colour(cell));
+            }
+        }
+        image
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> Rgb {
+        self.pixels[y * self.width + x]
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, colour: Rgb) {
+        self.pixels[y * self.width + x] = colour;
+    }
+
+    // each pixel becomes a square of `factor` by `factor` pixels
+    pub fn scale(&self, factor: usize) -> Result<Image, AocError> {
+        if factor == 0 {
+            return Err(AocError::InvalidToken(
+                "an image cannot be scaled by 0".to_string(),
+            ));
+        }
+
+        let mut image = Image::new(self.width * factor, self.height * factor);
+        for y in 0..image.height {
+            for x in 0..image.width {
+                image.set(x, y, self.get(x / factor, y / factor));
+            }
+        }
+        Ok(image)
+    }
+
+    // binary PPM (P6)
+    pub fn to_ppm(&self) -> Vec<u8> {
+        let mut out = format!("P6\n{} {}\n255\n", self.width, self.height).into_bytes();
+        for pixel in &self.pixels {
+            out.extend_from_slice(pixel);
+        }
+        out
+    }
+
+    // 8 bits RGB PNG, the image data being stored in uncompressed deflate blocks
+    pub fn to_png(&self) -> Vec<u8> {
+        let mut raw = Vec::with_capacity(self.height * (1 + 3 * self.width));
+        for row in self.pixels.chunks(self.width.max(1)).take(self.height) {
+            raw.push(0); // no filter
+            for pixel in row {
+                raw.extend_from_slice(pixel);
+            }
+        }
+
+        let mut header = vec![];
+        header.extend_from_slice(&(self.width as u32).to_be_bytes());
+        header.extend_from_slice(&(self.height as u32).to_be_bytes());
+        // bit depth, colour type (RGB), compression, filter, interlace
+        header.extend_from_slice(&[8, 2, 0, 0, 0]);
+
+        let mut out = vec![0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+        png_chunk(&mut out, b"IHDR", &header);
+        png_chunk(&mut out, b"IDAT", &zlib_stored(&raw));
+        png_chunk(&mut out, b"IEND", &[]);
+        out
+    }
+
+    // the format is chosen from the extension of the file, .ppm or .png
+    pub fn save(&self, path: &str) -> Result<(), AocError> {
+        let bytes = match Path::new(path).extension().and_then(|e| e.to_str()) {
+            Some("ppm") => self.to_ppm(),
+            Some("png") => self.to_png(),
+            _ => {
+                return Err(AocError::InvalidToken(format!(
+                    "unknown image format {:?}",
+                    path
+                )))
+            }
+        };
+        std::fs::write(path, bytes)?;
+        Ok(())
+    }
+}
+
+fn png_chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = out.len();
+    out.extend_from_slice(kind);
+    out.extend_from_slice(data);
+    let crc = crc32(&out[start..]);
+    out.extend_from_slice(&crc.to_be_bytes());
+}
+
+// zlib stream made of stored (uncompressed) deflate blocks
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x78, 0x01];
+    let mut blocks = data.chunks(0xffff).peekable();
+    if blocks.peek().is_none() {
+        // an empty final block
+        out.extend_from_slice(&[1, 0, 0, 0xff, 0xff]);
+    }
+    while let Some(block) = blocks.next() {
+        out.push(if blocks.peek().is_none() { 1 } else { 0 });
+        let len = block.len() as u16;
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xffff_ffffu32;
+    for &byte in data {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in data {
+        a = (a + u32::from(byte)) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+#[test]
+fn test_checksums() {
+    assert_eq!(0xcbf4_3926, crc32(b"123456789"));
+    assert_eq!(0x11e6_0398, adler32(b"Wikipedia"));
+}
+
+#[test]
+fn test_image() {
+    let grid = vec![vec![true, false], vec![false]];
+    let image = Image::from_grid(&grid, |&c| if c { [255, 0, 0] } else { [0, 0, 255] });
+    assert_eq!((2, 2), (image.width(), image.height()));
+    assert_eq!([0, 0, 0], image.get(1, 1));
+
+    let ppm = image.to_ppm();
+    assert_eq!(b"P6\n2 2\n255\n\xff\x00\x00\x00\x00\xff"[..], ppm[..17]);
+    assert_eq!(11 + 2 * 2 * 3, ppm.len());
+
+    let scaled = image.scale(3).unwrap();
+    assert_eq!((6, 6), (scaled.width(), scaled.height()));
+    assert_eq!([255, 0, 0], scaled.get(2, 2));
+    assert_eq!([0, 0, 255], scaled.get(3, 2));
+    assert!(image.scale(0).is_err());
+
+    let png = image.to_png();
+    assert_eq!(b"\x89PNG\r\n\x1a\n"[..], png[..8]);
+    // IHDR, then the 2 rows of a filter byte and 2 pixels stored in a single block
+    assert_eq!(b"IHDR"[..], png[12..16]);
+    assert_eq!(b"IDAT"[..], png[37..41]);
+    assert_eq!(
+        2 + 5 + 2 * 7 + 4,
+        u32::from_be_bytes([png[33], png[34], png[35], png[36]])
+    );
+    // the IEND chunk and its well known CRC
+    assert_eq!(
+        b"\x00\x00\x00\x00IEND\xae\x42\x60\x82"[..],
+        png[png.len() - 12..]
+    );
+}
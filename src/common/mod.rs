@@ -1,2 +1,5 @@
+pub mod automaton;
+pub mod elfcode;
 pub mod error;
+pub mod image;
 pub mod response;
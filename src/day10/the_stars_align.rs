@@ -1,6 +1,9 @@
 use crate::common::error::AocError;
 use crate::common::response::AocResponse;
 
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+use std::fmt;
 use std::fs::File;
 use std::io::{BufRead, BufReader};
 use std::str::FromStr;
@@ -40,9 +43,10 @@ impl FromStr for Star {
 }
 
 impl Star {
-    fn next(&self) -> Star {
-        let pos_x = self.pos_x + self.vel_x;
-        let pos_y = self.pos_y + self.vel_y;
+    // the star after `dt` seconds (possibly negative)
+    fn at(&self, dt: i32) -> Star {
+        let pos_x = self.pos_x + self.vel_x * dt;
+        let pos_y = self.pos_y + self.vel_y * dt;
         let vel_x = self.vel_x;
         let vel_y = self.vel_y;
         Star {
@@ -54,6 +58,35 @@ impl Star {
     }
 }
 
+// letters of the font used by the puzzle, 6 columns wide and 10 rows high
+const FONT_LETTERS: &str = "ABCEFGHJKLNPRXZ";
+const FONT: &str = r#"
+..##..  #####.  .####.  ######  ######  .####.  #....#  ...###  #....#  #.....  #....#  #####.  #####.  #....#  ######
+.#..#.  #....#  #....#  #.....  #.....  #....#  #....#  ....#.  #...#.  #.....  ##...#  #....#  #....#  #....#  .....#
+#....#  #....#  #.....  #.....  #.....  #.....  #....#  ....#.  #..#..  #.....  ##...#  #....#  #....#  .#..#.  .....#
+#....#  #....#  #.....  #.....  #.....  #.....  #....#  ....#.  #.#...  #.....  #.#..#  #....#  #....#  .#..#.  ....#.
+#....#  #####.  #.....  #####.  #####.  #.....  ######  ....#.  ##....  #.....  #.#..#  #####.  #####.  ..##..  ...#..
+######  #....#  #.....  #.....  #.....  #..###  #....#  ....#.  ##....  #.....  #..#.#  #.....  #..#..  ..##..  ..#...
+#....#  #....#  #.....  #.....  #.....  #....#  #....#  ....#.  #.#...  #.....  #..#.#  #.....  #...#.  .#..#.  .#....
+#....#  #....#  #.....  #.....  #.....  #....#  #....#  #...#.  #..#..  #.....  #...##  #.....  #...#.  .#..#.  #.....
+#....#  #....#  #....#  #.....  #.....  #...##  #....#  #...#.  #...#.  #.....  #...##  #.....  #....#  #....#  #.....
+#....#  #####.  .####.  ######  #.....  .###.#  #....#  .###..  #....#  ######  #....#  #.....  #....#  #....#  ######
+"#;
+
+lazy_static! {
+    static ref GLYPHS: HashMap<String, char> = {
+        let rows: Vec<&str> = FONT.trim().lines().collect();
+        FONT_LETTERS
+            .chars()
+            .enumerate()
+            .map(|(i, c)| {
+                let glyph: Vec<&str> = rows.iter().map(|row| &row[i * 8..i * 8 + 6]).collect();
+                (glyph.join("\n"), c)
+            })
+            .collect()
+    };
+}
+
 struct Field {
     stars: Vec<Star>,
     time: u32,
@@ -64,47 +97,116 @@ impl Field {
         Field { stars, time: 0 }
     }
 
-    fn step(self: &mut Self) {
+    fn move_to(&mut self, time: u32) {
+        let dt = time as i32 - self.time as i32;
         for s in &mut self.stars {
-            *s = s.next();
+            *s = s.at(dt);
         }
-        self.time += 1
+        self.time = time;
     }
 
-    fn display(&mut self) {
-        let min_x = self.stars.iter().map(|s| s.pos_x).min().unwrap();
-        let max_x = self.stars.iter().map(|s| s.pos_x).max().unwrap();
-        let min_y = self.stars.iter().map(|s| s.pos_y).min().unwrap();
-        let max_y = self.stars.iter().map(|s| s.pos_y).max().unwrap();
+    fn bounding_box(&self) -> (i32, i32, i32, i32) {
+        let min_x = self.stars.iter().map(|s| s.pos_x).min().unwrap_or(0);
+        let max_x = self.stars.iter().map(|s| s.pos_x).max().unwrap_or(0);
+        let min_y = self.stars.iter().map(|s| s.pos_y).min().unwrap_or(0);
+        let max_y = self.stars.iter().map(|s| s.pos_y).max().unwrap_or(0);
+        (min_x, max_x, min_y, max_y)
+    }
 
-        // only display images of reasonable size
-        if (max_x - min_x) < 100 && (max_y - min_y) < 15 {
-            let mut pic = vec![false; ((max_x - min_x + 1) * (max_y - min_y + 1)) as usize];
-            let access = |x, y| (x - min_x + (max_x - min_x + 1) * (y - min_y)) as usize;
+    // width + height of the bounding box, a convex function of the time
+    fn size(&self) -> i64 {
+        let (min_x, max_x, min_y, max_y) = self.bounding_box();
+        i64::from(max_x - min_x) + i64::from(max_y - min_y)
+    }
 
-            for star in &self.stars {
-                pic[access(star.pos_x, star.pos_y)] = true;
+    // move the field to the time where the stars are the closest to each other
+    fn converge(&mut self) {
+        // first estimate when the stars going up the fastest meet the ones going down the fastest
+        let up = self.stars.iter().min_by_key(|s| s.vel_y);
+        let down = self.stars.iter().max_by_key(|s| s.vel_y);
+        if let (Some(up), Some(down)) = (up, down) {
+            if down.vel_y > up.vel_y {
+                let t = (up.pos_y - down.pos_y) / (down.vel_y - up.vel_y);
+                self.move_to(t.max(0) as u32);
             }
+        }
 
-            println!("Day 10: The Stars Align (1/2):");
-            for y in min_y..=max_y {
-                for x in min_x..=max_x {
-                    if pic[access(x, y)] {
-                        print!("#");
-                    } else {
-                        print!(".");
-                    }
+        // then walk down hill, the size being convex this reaches the global minimum
+        let mut size = self.size();
+        for &dt in &[1, -1] {
+            loop {
+                let time = self.time as i32 + dt;
+                if time < 0 {
+                    break;
+                }
+                self.move_to(time as u32);
+                let next = self.size();
+                if next >= size {
+                    self.move_to((time - dt) as u32);
+                    break;
                 }
-                println!();
+                size = next;
+            }
+        }
+    }
+
+    fn pixels(&self) -> Vec<Vec<bool>> {
+        let (min_x, max_x, min_y, max_y) = self.bounding_box();
+        let mut pic = vec![vec![false; (max_x - min_x + 1) as usize]; (max_y - min_y + 1) as usize];
+        for star in &self.stars {
+            pic[(star.pos_y - min_y) as usize][(star.pos_x - min_x) as usize] = true;
+        }
+        pic
+    }
+
+    // read the message, letters being separated by empty columns
+    fn message(&self) -> Option<String> {
+        let pic = self.pixels();
+        let width = pic.first().map_or(0, |row| row.len());
+        let empty_column = |x: usize| pic.iter().all(|row| !row[x]);
+
+        let mut message = String::new();
+        let mut x = 0;
+        while x < width {
+            if empty_column(x) {
+                x += 1;
+                continue;
+            }
+            let start = x;
+            while x < width && !empty_column(x) {
+                x += 1;
             }
-            println!("Day 10: The Stars Align (2/2): {}", self.time);
+
+            let glyph = pic
+                .iter()
+                .map(|row| {
+                    row[start..x]
+                        .iter()
+                        .map(|&p| if p { '#' } else { '.' })
+                        .collect()
+                })
+                .collect::<Vec<String>>()
+                .join("\n");
+            message.push(*GLYPHS.get(&glyph)?);
         }
+
+        Some(message)
     }
 }
 
-fn read_file() -> Result<Vec<Star>, AocError> {
-    //let filename = "input/input10_debug.txt";
-    let filename = "input/input10.txt";
+impl fmt::Display for Field {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for row in self.pixels() {
+            for p in row {
+                write!(f, "{}", if p { '#' } else { '.' })?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+fn read_file(filename: &str) -> Result<Vec<Star>, AocError> {
     let file = File::open(filename)?;
     let reader = BufReader::new(file);
 
@@ -118,18 +220,53 @@ fn read_file() -> Result<Vec<Star>, AocError> {
     Ok(file_lines)
 }
 
-pub fn answer1() -> Result<AocResponse<usize>, AocError> {
-    let stars = read_file()?;
+pub fn answer1() -> Result<AocResponse<String>, AocError> {
+    let stars = read_file("input/input10.txt")?;
     let mut field = Field::new(stars);
+    field.converge();
 
-    for _i in 0..12000 {
-        field.step();
-        field.display();
-    }
+    let message = field.message().ok_or(AocError::ComputeNotFound)?;
+
+    Ok(AocResponse::new(10, 1, "The Stars Align", message))
+}
 
-    // time: 10036
-    // JJXZHKFP
+pub fn answer2() -> Result<AocResponse<u32>, AocError> {
+    let stars = read_file("input/input10.txt")?;
+    let mut field = Field::new(stars);
+    field.converge();
+
+    Ok(AocResponse::new(10, 2, "The Stars Align", field.time))
+}
+
+#[test]
+fn test_converge() {
+    let stars = read_file("input/input10_debug.txt").unwrap();
+    let mut field = Field::new(stars);
+    field.converge();
+
+    assert_eq!(3, field.time);
+    assert_eq!(
+        r#"#...#..###
+#...#...#.
+#...#...#.
+#####...#.
+#...#...#.
+#...#...#.
+#...#...#.
+#...#..###
+"#,
+        field.to_string()
+    );
+    // the example uses a smaller font
+    assert_eq!(None, field.message());
+}
+
+#[test]
+fn test_message() {
+    let stars = read_file("input/input10.txt").unwrap();
+    let mut field = Field::new(stars);
+    field.converge();
 
-    // FIXME
-    Err(AocError::ComputeNotFound)
+    assert_eq!(10036, field.time);
+    assert_eq!(Some("JJXZHKFP".to_string()), field.message());
 }
@@ -1,152 +1,351 @@
 use crate::common::error::AocError;
 use crate::common::response::AocResponse;
 
-use std::collections::HashSet;
+use std::collections::HashMap;
+use std::ops::RangeInclusive;
+use std::str::FromStr;
 
+// which neighbourhoods grow a plant, for neighbourhoods of any odd width. A neighbourhood is
+// the index in `alive` whose bits are the pots from the leftmost (highest bit) to the rightmost.
 #[derive(Debug)]
-struct Plantation {
-    state: String,
-    offset: isize,
+pub struct Rules {
+    width: usize,
+    alive: Vec<bool>,
 }
 
-impl Plantation {
-    fn step(&self, rules: &HashSet<String>) -> Plantation {
-        let mut next_state = "".to_string();
-        for i in 0..(self.state.len() - 4) {
-            let current = &self.state[i..][..5];
-            next_state.push(if rules.contains(current) { '#' } else { '.' });
+impl Rules {
+    // the rules growing a plant for each of the `patterns`, neighbourhoods of `width` pots
+    // such as "..#.#", and leaving the pot empty for any other one
+    pub fn new(width: usize, patterns: &[&str]) -> Result<Rules, AocError> {
+        if width.is_multiple_of(2) || width > 25 {
+            return Err(AocError::InvalidToken(format!(
+                "the neighbourhood should have an odd width up to 25, not {}",
+                width
+            )));
+        }
+
+        let mut alive = vec![false; 1 << width];
+        for pattern in patterns {
+            if pattern.len() != width {
+                return Err(AocError::InvalidToken(format!(
+                    "{:?} is not {} pots wide",
+                    pattern, width
+                )));
+            }
+            let index = pattern.chars().try_fold(0, |index, c| match c {
+                '#' => Ok(index << 1 | 1),
+                '.' => Ok(index << 1),
+                _ => Err(AocError::InvalidToken(format!("unexpected {:?}", c))),
+            })?;
+            alive[index] = true;
         }
 
-        // find the first and last #
-        let first = next_state.bytes().position(|c| c == b'#').unwrap();
-        let last = next_state.bytes().rposition(|c| c == b'#').unwrap();
+        // plants growing out of nothing would fill the infinite row of pots
+        if alive[0] {
+            return Err(AocError::InvalidToken(
+                "a plant cannot grow without any plant around".to_string(),
+            ));
+        }
+
+        Ok(Rules { width, alive })
+    }
+
+    fn radius(&self) -> isize {
+        (self.width / 2) as isize
+    }
+}
 
-        let mut formated_state = String::from("....");
-        formated_state.push_str(&next_state[first..][..=last - first]);
-        formated_state.push_str("....");
+// the pots from the first plant to the last one, as a bitset
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Plantation {
+    bits: Vec<u64>,
+    len: usize,
+    // number of the first plant
+    first: isize,
+}
 
-        let formated_offset = self.offset + 2 - first as isize;
+impl Plantation {
+    // the pots of `state`, the first one being the pot `first`
+    pub fn new(state: &str, first: isize) -> Plantation {
+        let (bits, len) = state.chars().fold((vec![], 0), |(mut bits, len), c| {
+            set(&mut bits, len, c == '#');
+            (bits, len + 1)
+        });
+        Plantation::trimmed(&bits, len, first)
+    }
+
+    // the plantation of the `len` pots of `bits`, the first one being the pot `start`, without
+    // its empty pots on both sides
+    fn trimmed(bits: &[u64], len: usize, start: isize) -> Plantation {
+        let plant = |i: &usize| bits[i / 64] >> (i % 64) & 1 == 1;
+        let from = match (0..len).find(plant) {
+            Some(from) => from,
+            None => {
+                return Plantation {
+                    bits: vec![],
+                    len: 0,
+                    first: 0,
+                }
+            }
+        };
+        let to = (0..len).rfind(plant).unwrap();
 
+        let mut trimmed = vec![];
+        for i in from..=to {
+            set(&mut trimmed, i - from, plant(&i));
+        }
         Plantation {
-            state: formated_state,
-            offset: formated_offset,
+            bits: trimmed,
+            len: to - from + 1,
+            first: start + from as isize,
         }
     }
 
-    fn sum(&self) -> isize {
-        self.state
-            .chars()
-            .enumerate()
-            .filter(|(_i, c)| *c == '#')
-            .map(|(i, _c)| i as isize - self.offset)
+    pub fn get(&self, pot: isize) -> bool {
+        let i = pot - self.first;
+        i >= 0 && (i as usize) < self.len && self.bits[i as usize / 64] >> (i % 64) & 1 == 1
+    }
+
+    fn last(&self) -> isize {
+        self.first + self.len as isize - 1
+    }
+
+    pub fn step(&self, rules: &Rules) -> Plantation {
+        if self.len == 0 {
+            return self.clone();
+        }
+
+        // the neighbourhood slides over the pots, entering from the right
+        let r = rules.radius();
+        let mask = (1 << rules.width) - 1;
+        let start = self.first - r;
+        let mut neighbourhood = 0;
+        for pot in start - r..start + r {
+            neighbourhood = (neighbourhood << 1 | self.get(pot) as usize) & mask;
+        }
+
+        let mut bits = vec![];
+        let mut len = 0;
+        for pot in start..=self.last() + r {
+            neighbourhood = (neighbourhood << 1 | self.get(pot + r) as usize) & mask;
+            set(&mut bits, len, rules.alive[neighbourhood]);
+            len += 1;
+        }
+
+        Plantation::trimmed(&bits, len, start)
+    }
+
+    pub fn count(&self) -> isize {
+        self.bits.iter().map(|w| w.count_ones() as isize).sum()
+    }
+
+    pub fn sum(&self) -> isize {
+        (self.first..=self.last())
+            .filter(|&pot| self.get(pot))
             .sum()
     }
+
+    // the same plants, `by` pots further right
+    fn shifted(&self, by: isize) -> Plantation {
+        Plantation {
+            first: self.first + by,
+            ..self.clone()
+        }
+    }
+
+    // the plantation once it has grown `generations` times. As soon as the plants come back to
+    // a pattern seen before, possibly somewhere else, the remaining generations are skipped:
+    // every `period` generations the plants move of `drift` pots.
+    pub fn generation(&self, rules: &Rules, generations: u64) -> Plantation {
+        let mut seen = HashMap::new();
+        let mut current = self.clone();
+
+        let mut generation = 0;
+        while generation < generations {
+            if let Some(&(before, first)) = seen.get(&current.bits) {
+                let period = generation - before;
+                let drift = current.first - first;
+                let cycles = (generations - generation) / period;
+                for _ in 0..(generations - generation) % period {
+                    current = current.step(rules);
+                }
+                return current.shifted(cycles as isize * drift);
+            }
+
+            seen.insert(current.bits.clone(), (generation, current.first));
+            current = current.step(rules);
+            generation += 1;
+        }
+
+        current
+    }
+
+    // the given pots as the puzzle shows them
+    pub fn render(&self, pots: RangeInclusive<isize>) -> String {
+        pots.map(|pot| if self.get(pot) { '#' } else { '.' })
+            .collect()
+    }
 }
 
-fn generations(s: &str) -> isize {
-    let mut lines = s.split('\n');
-    let mut state = lines.next().unwrap().split(' ').nth(2).unwrap().to_string();
+// the puzzle input: the initial state of the pots and the rules making them grow
+#[derive(Debug)]
+pub struct Garden {
+    initial: Plantation,
+    rules: Rules,
+}
+
+impl FromStr for Garden {
+    type Err = AocError;
+
+    fn from_str(s: &str) -> Result<Garden, AocError> {
+        let error = |line: usize, msg: String| {
+            AocError::InvalidToken(format!("line {}: {}", line + 1, msg))
+        };
+        let pots = |line: usize, pots: &str| match pots.chars().find(|&c| c != '#' && c != '.') {
+            Some(c) => Err(error(line, format!("unexpected {:?}", c))),
+            None => Ok(()),
+        };
+
+        let mut lines = s.lines().enumerate().filter(|(_, l)| !l.trim().is_empty());
+        let initial = match lines.next() {
+            Some((i, line)) => {
+                let state = line
+                    .trim()
+                    .strip_prefix("initial state: ")
+                    .ok_or_else(|| error(i, "expected the initial state".to_string()))?;
+                pots(i, state)?;
+                Plantation::new(state, 0)
+            }
+            None => return Err(AocError::InvalidToken("empty input".to_string())),
+        };
+
+        let mut outcomes: HashMap<&str, bool> = HashMap::new();
+        let mut width = None;
+        for (i, line) in lines {
+            let (pattern, outcome) = match line.trim().split(" => ").collect::<Vec<_>>()[..] {
+                [pattern, "#"] => (pattern, true),
+                [pattern, "."] => (pattern, false),
+                _ => return Err(error(i, format!("invalid rule {:?}", line.trim()))),
+            };
+            pots(i, pattern)?;
+            if *width.get_or_insert(pattern.len()) != pattern.len() {
+                return Err(error(
+                    i,
+                    format!("{:?} is not {} pots wide", pattern, width.unwrap()),
+                ));
+            }
+            if outcomes.insert(pattern, outcome).is_some() {
+                return Err(error(i, format!("a second rule for {:?}", pattern)));
+            }
+        }
+
+        let width = width.ok_or_else(|| AocError::InvalidToken("no rules".to_string()))?;
+        let alive: Vec<&str> = outcomes
+            .into_iter()
+            .filter(|&(_, outcome)| outcome)
+            .map(|(pattern, _)| pattern)
+            .collect();
+        let rules = Rules::new(width, &alive)?;
+
+        Ok(Garden { initial, rules })
+    }
+}
+
+impl Garden {
+    pub fn initial(&self) -> &Plantation {
+        &self.initial
+    }
+
+    pub fn rules(&self) -> &Rules {
+        &self.rules
+    }
 
-    // add 4 . on each side
-    state.insert_str(0, "....");
-    state.push_str("....");
+    pub fn generation(&self, generation: u64) -> Plantation {
+        self.initial.generation(&self.rules, generation)
+    }
 
-    lines.next(); // skip blank line
-    let rules: HashSet<String> = lines
-        .filter(|l| l.ends_with(" => #")) // onyl rule that produce alive elements
-        .map(|s| s.split(' ').next().unwrap().parse::<String>().unwrap())
-        .collect();
+    pub fn sum_at(&self, generation: u64) -> isize {
+        self.generation(generation).sum()
+    }
 
-    let mut p = Plantation { state, offset: 4 }; // the center is at offset 4 because we added 4 characters
-    (0..20).for_each(|_| p = p.step(&rules));
+    // the first generations, as the puzzle shows them
+    pub fn render(&self, generations: u64, pots: RangeInclusive<isize>) -> String {
+        let mut out = String::new();
+        let mut current = self.initial.clone();
+        for generation in 0..=generations {
+            out.push_str(&format!(
+                "{:2}: {}\n",
+                generation,
+                current.render(pots.clone())
+            ));
+            current = current.step(&self.rules);
+        }
+        out
+    }
+}
 
-    p.sum()
+// set the i-th bit of a growable bitset
+fn set(bits: &mut Vec<u64>, i: usize, value: bool) {
+    if bits.len() <= i / 64 {
+        bits.resize(i / 64 + 1, 0);
+    }
+    if value {
+        bits[i / 64] |= 1 << (i % 64);
+    } else {
+        bits[i / 64] &= !(1 << (i % 64));
+    }
 }
 
 pub fn answer1() -> Result<AocResponse<isize>, AocError> {
     let s = std::fs::read_to_string("input/input12.txt")?;
+    let garden = Garden::from_str(&s)?;
 
     Ok(AocResponse::new(
         12,
         1,
         "Subterranean Sustainability",
-        generations(&s),
+        garden.sum_at(20),
     ))
 }
 
 pub fn answer2() -> Result<AocResponse<isize>, AocError> {
     let s = std::fs::read_to_string("input/input12.txt")?;
-
-    let mut lines = s.split('\n');
-    let mut state = lines.next().unwrap().split(' ').nth(2).unwrap().to_string();
-
-    // add 4 . on each side
-    state.insert_str(0, "....");
-    state.push_str("....");
-
-    lines.next(); // skip blank line
-    let rules: HashSet<String> = lines
-        .filter(|l| l.ends_with(" => #")) // onyl rule that produce alive elements
-        .map(|s| s.split(' ').next().unwrap().parse::<String>().unwrap())
-        .collect();
-
-    let mut current = Plantation { state, offset: 4 }; // the center is at offset 4 because we added 4 characters
-    let mut i = 0;
-
-    let result = loop {
-        let next = current.step(&rules);
-        // if the state is indentical (but the offset might change), then we are stable and we break
-        if next.state == current.state {
-            let diff = next.sum() - current.sum();
-            // rust is awsome, you can return a value in a break!!!
-            break (50_000_000_000 - i) * diff + current.sum();
-        }
-        i += 1;
-        current = next;
-    };
+    let garden = Garden::from_str(&s)?;
 
     Ok(AocResponse::new(
         12,
         2,
         "Subterranean Sustainability",
-        result,
+        garden.sum_at(50_000_000_000),
     ))
 }
 
 #[test]
 fn test_step() {
-    let p = Plantation {
-        state: "....#..#.#..##......###...###....".to_string(),
-        offset: 4,
-    };
-    let mut rules = HashSet::new();
-    rules.insert("...##".to_string());
-    rules.insert("..#..".to_string());
-    rules.insert(".#...".to_string());
-    rules.insert(".#.#.".to_string());
-    rules.insert(".#.##".to_string());
-    rules.insert(".##..".to_string());
-    rules.insert(".####".to_string());
-    rules.insert("#.#.#".to_string());
-    rules.insert("#.###".to_string());
-    rules.insert("##.#.".to_string());
-    rules.insert("##.##".to_string());
-    rules.insert("###..".to_string());
-    rules.insert("###.#".to_string());
-    rules.insert("####.".to_string());
+    let p = Plantation::new("#..#.#..##......###...###", 0);
+    let rules = Rules::new(
+        5,
+        &[
+            "...##", "..#..", ".#...", ".#.#.", ".#.##", ".##..", ".####", "#.#.#", "#.###",
+            "##.#.", "##.##", "###..", "###.#", "####.",
+        ],
+    )
+    .unwrap();
 
     let new_p = p.step(&rules);
 
-    assert_eq!(new_p.state, "....#...#....#.....#..#..#..#....".to_string());
-    assert_eq!(new_p.offset, 4);
+    assert_eq!(new_p, Plantation::new("#...#....#.....#..#..#..#", 0));
     assert_eq!(new_p.sum(), 91);
+
+    // the plants spread left of the pot 0
+    let new_p = new_p.step(&rules);
+    assert_eq!(new_p, Plantation::new("##..##...##....#..#..#..##", 0));
+    let new_p = new_p.step(&rules);
+    assert_eq!(new_p, Plantation::new("#.#...#..#.#....#..#..#...#", -1));
 }
 
-#[test]
-fn test_generation() {
-    assert_eq!(
-        generations(
-            r#"initial state: #..#.#..##......###...###
+#[cfg(test)]
+const EXAMPLE: &str = r#"initial state: #..#.#..##......###...###
 
 ...## => #
 ..#.. => #
@@ -162,8 +361,98 @@ fn test_generation() {
 ###.. => #
 ###.# => #
 ####. => #
-"#
-        ),
-        325
+"#;
+
+#[test]
+fn test_generation() {
+    let garden = Garden::from_str(EXAMPLE).unwrap();
+    assert_eq!(325, garden.sum_at(20));
+    assert_eq!(
+        Plantation::new("#....##....#####...#######....#.#..##", -2),
+        garden.generation(20)
+    );
+}
+
+#[test]
+fn test_render() {
+    let garden = Garden::from_str(EXAMPLE).unwrap();
+    assert_eq!(
+        r#" 0: ...#..#.#..##......###...###...........
+ 1: ...#...#....#.....#..#..#..#...........
+ 2: ...##..##...##....#..#..#..##..........
+ 3: ..#.#...#..#.#....#..#..#...#..........
+"#,
+        garden.render(3, -3..=35)
+    );
+}
+
+#[test]
+fn test_parse_errors() {
+    let err = |s: &str| match Garden::from_str(s) {
+        Err(AocError::InvalidToken(msg)) => msg,
+        other => panic!("unexpected {:?}", other),
+    };
+    assert_eq!(
+        "line 1: expected the initial state",
+        err("#..#\n\n..#.. => #")
+    );
+    assert_eq!("line 1: unexpected 'x'", err("initial state: #x#"));
+    assert_eq!(
+        "line 3: invalid rule \"..#.. -> #\"",
+        err("initial state: #\n\n..#.. -> #")
+    );
+    assert_eq!(
+        "line 4: \"..#\" is not 5 pots wide",
+        err("initial state: #\n\n..#.. => #\n..# => .")
+    );
+    assert_eq!(
+        "line 4: a second rule for \"..#..\"",
+        err("initial state: #\n\n..#.. => #\n..#.. => .")
+    );
+    assert_eq!("no rules", err("initial state: #\n"));
+}
+
+#[test]
+fn test_rules() {
+    assert!(Rules::new(4, &["..#."]).is_err());
+    assert!(Rules::new(3, &["..#."]).is_err());
+    assert!(Rules::new(3, &["...", "#.#"]).is_err());
+}
+
+#[test]
+fn test_wide_plants() {
+    // a lone plant going left 3 pots at each generation
+    let rules = Rules::new(7, &["......#"]).unwrap();
+    let p = Plantation::new("#", 0);
+    assert_eq!(Plantation::new("#", -3), p.step(&rules));
+    assert_eq!(
+        -3 * 50_000_000_000,
+        p.generation(&rules, 50_000_000_000).sum()
+    );
+
+    // more than a word of plants, all of them staying
+    let rules = Rules::new(1, &["#"]).unwrap();
+    let p = Plantation::new(&"#.".repeat(40), 5);
+    assert_eq!(2, p.bits.len());
+    assert_eq!(p, p.step(&rules));
+    assert_eq!(
+        (0..40).map(|i| 5 + 2 * i).sum::<isize>(),
+        p.generation(&rules, 1_000).sum()
+    );
+}
+
+#[test]
+fn test_drifting_cycle() {
+    // one plant becomes two, which become one plant two pots left of where it was
+    let rules = Rules::new(3, &["..#", ".#."]).unwrap();
+    let p = Plantation::new("#", 0);
+    assert_eq!(Plantation::new("##", -1), p.step(&rules));
+    assert_eq!(Plantation::new("#", -2), p.step(&rules).step(&rules));
+
+    assert_eq!(
+        Plantation::new("#", -10_000_000_000),
+        p.generation(&rules, 10_000_000_000)
     );
+    assert_eq!(-20_000_000_001, p.generation(&rules, 10_000_000_001).sum());
+    assert_eq!(-5, p.generation(&rules, 3).sum());
 }
@@ -1,16 +1,21 @@
 use crate::common::error::AocError;
 use crate::common::response::AocResponse;
 
+use std::collections::HashMap;
 use std::fmt;
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
 
 #[derive(Debug)]
-struct World {
+pub struct World {
     lines: Vec<Vec<u8>>, // u8 an octet (the smallest we can use to store a state)
     carts: Vec<Cart>,
 }
 
 #[derive(Debug)]
 struct Cart {
+    id: usize, // order of the cart in the input
     x: usize,
     y: usize,
     dir: Dir,
@@ -27,9 +32,77 @@ enum Dir {
     DOWN,
 }
 
+// two carts running into each other
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Crash {
+    pub tick: usize,
+    pub x: usize,
+    pub y: usize,
+    // ids of the moving cart, then of the one it ran into
+    pub carts: (usize, usize),
+}
+
+// what happened during a tick of the simulation
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Tick {
+    // starting at 1 for the first tick
+    pub tick: usize,
+    pub crashes: Vec<Crash>,
+    // (id, x, y) of the carts still running at the end of the tick, in reading order
+    pub survivors: Vec<(usize, usize, usize)>,
+}
+
+// run the simulation tick by tick, until at most one cart is left. If the carts come back to a
+// state they were already in, they will loop forever: an error is returned and the iteration stops
+pub struct Ticks {
+    world: World,
+    tick: usize,
+    // tick at which each state was seen, since the last crash
+    seen: HashMap<Vec<(usize, usize, u8, u8)>, usize>,
+}
+
+impl Iterator for Ticks {
+    type Item = Result<Tick, AocError>;
+
+    fn next(&mut self) -> Option<Result<Tick, AocError>> {
+        if self.world.carts.len() < 2 {
+            return None;
+        }
+
+        if self.tick == 0 {
+            self.seen.insert(self.world.state(), 0);
+        }
+
+        self.tick += 1;
+        let crashes = self.world.step(self.tick);
+
+        if !crashes.is_empty() {
+            // carts are fewer now, earlier states cannot come back
+            self.seen.clear();
+        }
+        if let Some(previous) = self.seen.insert(self.world.state(), self.tick) {
+            self.world.carts.clear();
+            return Some(Err(AocError::InfiniteLoop(format!(
+                "the carts are at tick {} in the same state as at tick {}",
+                self.tick, previous
+            ))));
+        }
+
+        let mut survivors: Vec<_> = self.world.carts.iter().map(|c| (c.id, c.x, c.y)).collect();
+        survivors.sort_by_key(|&(_, x, y)| (y, x));
+
+        Some(Ok(Tick {
+            tick: self.tick,
+            crashes,
+            survivors,
+        }))
+    }
+}
+
 impl Cart {
-    fn new(x: usize, y: usize, dir: Dir) -> Cart {
+    fn new(id: usize, x: usize, y: usize, dir: Dir) -> Cart {
         Cart {
+            id,
             x,
             y,
             dir,
@@ -39,22 +112,119 @@ impl Cart {
     }
 }
 
+const UP: u8 = 1;
+const DOWN: u8 = 2;
+const LEFT: u8 = 4;
+const RIGHT: u8 = 8;
+
+// the possible sets of exits of a piece of track, curves can be turned two ways
+fn exits(c: u8) -> &'static [u8] {
+    match c {
+        b'-' => &[LEFT | RIGHT],
+        b'|' => &[UP | DOWN],
+        b'+' => &[UP | DOWN | LEFT | RIGHT],
+        b'/' => &[RIGHT | DOWN, LEFT | UP],
+        b'\\' => &[LEFT | DOWN, RIGHT | UP],
+        _ => &[],
+    }
+}
+
+// check that every piece of track leads to another one connected back to it
+fn validate(lines: &[Vec<u8>]) -> Result<(), AocError> {
+    for (y, line) in lines.iter().enumerate() {
+        if let Some(x) = line.iter().position(|&c| c != b' ' && exits(c).is_empty()) {
+            return Err(AocError::InvalidToken(format!(
+                "line {}, column {}: unexpected {:?}",
+                y + 1,
+                x + 1,
+                line[x] as char
+            )));
+        }
+    }
+
+    let mut options: Vec<Vec<Vec<u8>>> = lines
+        .iter()
+        .map(|l| l.iter().map(|&c| exits(c).to_vec()).collect())
+        .collect();
+
+    let neighbour = |x: usize, y: usize, exit: u8| match exit {
+        UP if y > 0 => Some((x, y - 1, DOWN)),
+        DOWN if y + 1 < lines.len() => Some((x, y + 1, UP)),
+        LEFT if x > 0 => Some((x - 1, y, RIGHT)),
+        RIGHT => Some((x + 1, y, LEFT)),
+        _ => None,
+    };
+    let connected = |options: &[Vec<Vec<u8>>], x: usize, y: usize, exits: u8| {
+        [UP, DOWN, LEFT, RIGHT]
+            .iter()
+            .filter(|&&e| exits & e != 0)
+            .all(|&e| match neighbour(x, y, e) {
+                Some((nx, ny, back)) => options[ny]
+                    .get(nx)
+                    .is_some_and(|o| o.iter().any(|&n| n & back != 0)),
+                None => false,
+            })
+    };
+
+    // rule out the ways the curves cannot be turned, until nothing changes
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for y in 0..options.len() {
+            for x in 0..options[y].len() {
+                if exits(lines[y][x]).len() > 1 {
+                    let kept: Vec<u8> = options[y][x]
+                        .iter()
+                        .cloned()
+                        .filter(|&o| connected(&options, x, y, o))
+                        .collect();
+                    if kept.len() != options[y][x].len() {
+                        options[y][x] = kept;
+                        changed = true;
+                    }
+                }
+            }
+        }
+    }
+
+    for (y, line) in lines.iter().enumerate() {
+        for (x, &c) in line.iter().enumerate() {
+            let valid = match c {
+                b' ' => true,
+                b'/' | b'\\' => !options[y][x].is_empty(),
+                _ => connected(&options, x, y, options[y][x][0]),
+            };
+            if !valid {
+                return Err(AocError::InvalidToken(format!(
+                    "line {}, column {}: {:?} is not connected to the track",
+                    y + 1,
+                    x + 1,
+                    c as char
+                )));
+            }
+        }
+    }
+
+    Ok(())
+}
+
 impl World {
-    fn parse(s: &str) -> World {
-        let mut lines: Vec<Vec<u8>> = s
-            .split('\n')
-            .filter(|l| !l.is_empty())
-            .map(|s| s.to_string().as_bytes().to_vec())
-            .collect();
+    // the carts must stand on straight pieces of track
+    pub fn parse(s: &str) -> Result<World, AocError> {
+        let mut lines: Vec<Vec<u8>> = s.lines().map(|s| s.as_bytes().to_vec()).collect();
+        while lines.last().is_some_and(|l| l.is_empty()) {
+            lines.pop();
+        }
 
         let mut carts = vec![];
         for (y, line) in lines.iter().enumerate() {
             for (x, char) in line.iter().enumerate() {
+                let id = carts.len();
                 let cart = match char {
-                    b'>' => Some(Cart::new(x, y, Dir::RIGHT)),
-                    b'<' => Some(Cart::new(x, y, Dir::LEFT)),
-                    b'^' => Some(Cart::new(x, y, Dir::UP)),
-                    b'v' => Some(Cart::new(x, y, Dir::DOWN)),
+                    b'>' => Some(Cart::new(id, x, y, Dir::RIGHT)),
+                    b'<' => Some(Cart::new(id, x, y, Dir::LEFT)),
+                    b'^' => Some(Cart::new(id, x, y, Dir::UP)),
+                    b'v' => Some(Cart::new(id, x, y, Dir::DOWN)),
                     _ => None,
                 };
 
@@ -73,14 +243,41 @@ impl World {
             };
         }
 
-        World { lines, carts }
+        validate(&lines)?;
+
+        Ok(World { lines, carts })
     }
 
-    // return crashed carts
-    fn step(&mut self) -> Vec<Cart> {
+    // positions, directions and intersection counters of the carts, in reading order
+    fn state(&self) -> Vec<(usize, usize, u8, u8)> {
+        let mut state: Vec<_> = self
+            .carts
+            .iter()
+            .map(|c| (c.y, c.x, c.dir as u8, c.rot))
+            .collect();
+        state.sort();
+        state
+    }
+
+    pub fn ticks(self) -> Ticks {
+        Ticks {
+            world: self,
+            tick: 0,
+            seen: HashMap::new(),
+        }
+    }
+
+    // return the crashes of the tick, in the order they happened. Crashed carts are removed
+    // right away, so a cart can run over a crash site left during the same tick
+    fn step(&mut self, tick: usize) -> Vec<Crash> {
         self.carts.sort_by_key(|c| (c.y, c.x));
 
+        let mut crashes = vec![];
         for i in 0..self.carts.len() {
+            if self.carts[i].crashed {
+                continue;
+            }
+
             // move the cart
             match self.carts[i].dir {
                 Dir::UP => self.carts[i].y -= 1,
@@ -90,14 +287,18 @@ impl World {
             }
 
             // is there any chars with the same coords?
-            if let Some((idx, _)) = self
-                .carts
-                .iter()
-                .enumerate()
-                .find(|(idx, c)| *idx != i && c.x == self.carts[i].x && c.y == self.carts[i].y)
-            {
+            if let Some((idx, _)) = self.carts.iter().enumerate().find(|(idx, c)| {
+                *idx != i && !c.crashed && c.x == self.carts[i].x && c.y == self.carts[i].y
+            }) {
                 self.carts[idx].crashed = true;
                 self.carts[i].crashed = true;
+                crashes.push(Crash {
+                    tick,
+                    x: self.carts[i].x,
+                    y: self.carts[i].y,
+                    carts: (self.carts[i].id, self.carts[idx].id),
+                });
+                continue;
             }
 
             // find the next direction for the cart
@@ -123,75 +324,109 @@ impl World {
             self.carts[i].dir = dir;
         }
 
-        let mut crashed = vec![];
-        for i in (0..self.carts.len()).rev() {
-            // .rev() to remove the elements without index out of bound
-            if self.carts[i].crashed {
-                crashed.insert(0, self.carts.remove(i));
-            }
-        }
+        self.carts.retain(|c| !c.crashed);
 
-        crashed
+        crashes
     }
 }
 
-impl fmt::Display for World {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        writeln!(f, "World: {} active carts", &self.carts.len())?;
-        writeln!(f, "Carts: {:?}", &self.carts)?;
+impl World {
+    // the track with the carts and the given crash sites, as in the puzzle examples
+    fn render(&self, crashes: &[Crash]) -> String {
+        let mut out = String::new();
         for (y, line) in self.lines.iter().enumerate() {
             for (x, c) in line.iter().enumerate() {
-                if let Some(c) = &self.carts.iter().find(|c| c.x == x && c.y == y) {
-                    let char = match c.dir {
+                if crashes.iter().any(|c| c.x == x && c.y == y) {
+                    out.push('X');
+                } else if let Some(c) = &self.carts.iter().find(|c| c.x == x && c.y == y) {
+                    out.push(match c.dir {
                         Dir::UP => '^',
                         Dir::DOWN => 'v',
                         Dir::LEFT => '<',
                         Dir::RIGHT => '>',
-                    };
-                    write!(f, "{}", char as char)?;
+                    });
                 } else {
-                    write!(f, "{}", *c as char)?;
+                    out.push(*c as char);
                 }
             }
-            writeln!(f)?;
+            out.push('\n');
         }
 
-        Ok(())
+        out
     }
 }
 
-fn simulate1(s: &str) -> (usize, usize) {
-    let mut w = World::parse(s);
-    //println!("{}", w);
+impl fmt::Display for World {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.render(&[]))
+    }
+}
 
-    let crashed = loop {
-        let crashed = w.step();
-        //println!("{}", w);
-        //println!("crashed: {:?}", crashed);
-        if !crashed.is_empty() {
-            break crashed;
-        }
-    };
+// the initial state then every tick of the simulation, with the crashes of the tick
+fn frames(s: &str) -> Result<Vec<String>, AocError> {
+    let world = World::parse(s)?;
+    let mut frames = vec![world.to_string()];
+
+    let mut ticks = world.ticks();
+    while let Some(tick) = ticks.next() {
+        frames.push(ticks.world.render(&tick?.crashes));
+    }
+
+    Ok(frames)
+}
+
+// play the simulation back in the terminal
+pub fn replay(filename: &str, delay_ms: u64) -> Result<(), AocError> {
+    let s = std::fs::read_to_string(filename)?;
+    for (tick, frame) in frames(&s)?.iter().enumerate() {
+        // clear the terminal and move the cursor to the top left corner
+        print!("\x1b[2J\x1b[H");
+        println!("Tick {}:", tick);
+        print!("{}", frame);
+        thread::sleep(Duration::from_millis(delay_ms));
+    }
 
-    (crashed[0].x, crashed[0].y)
+    Ok(())
 }
 
-fn simulate2(s: &str) -> (usize, usize) {
-    let mut w = World::parse(s);
+// write each tick to its own file in `dir`, return the number of frames written
+pub fn write_frames(filename: &str, dir: &str) -> Result<usize, AocError> {
+    let s = std::fs::read_to_string(filename)?;
+    let frames = frames(&s)?;
 
-    loop {
-        w.step();
-        if w.carts.len() == 1 {
-            break;
+    std::fs::create_dir_all(dir)?;
+    for (tick, frame) in frames.iter().enumerate() {
+        std::fs::write(Path::new(dir).join(format!("tick_{:05}.txt", tick)), frame)?;
+    }
+
+    Ok(frames.len())
+}
+
+fn simulate1(s: &str) -> Result<(usize, usize), AocError> {
+    for tick in World::parse(s)?.ticks() {
+        if let Some(crash) = tick?.crashes.first() {
+            return Ok((crash.x, crash.y));
         }
     }
 
-    (w.carts[0].x, w.carts[0].y)
+    Err(AocError::ComputeNotFound)
+}
+
+fn simulate2(s: &str) -> Result<(usize, usize), AocError> {
+    let mut last = None;
+    for tick in World::parse(s)?.ticks() {
+        last = Some(tick?);
+    }
+
+    match last.as_ref().map(|t| &t.survivors[..]) {
+        Some([(_, x, y)]) => Ok((*x, *y)),
+        _ => Err(AocError::ComputeNotFound),
+    }
 }
 
 pub fn answer1() -> Result<AocResponse<String>, AocError> {
     let s = std::fs::read_to_string("input/input13.txt")?;
-    let result = simulate1(&s);
+    let result = simulate1(&s)?;
 
     Ok(AocResponse::new(
         13,
@@ -203,7 +438,7 @@ pub fn answer1() -> Result<AocResponse<String>, AocError> {
 
 pub fn answer2() -> Result<AocResponse<String>, AocError> {
     let s = std::fs::read_to_string("input/input13.txt")?;
-    let result = simulate2(&s);
+    let result = simulate2(&s)?;
 
     Ok(AocResponse::new(
         13,
@@ -216,5 +451,141 @@ pub fn answer2() -> Result<AocResponse<String>, AocError> {
 #[test]
 fn test() {
     let s = std::fs::read_to_string("input/input13_debug.txt").expect("cannot read file");
-    assert_eq!(simulate1(&s), (7, 3));
+    assert_eq!(simulate1(&s).unwrap(), (7, 3));
+}
+
+#[test]
+fn test_crash_timeline() {
+    let s = r#"/>-<\  
+|   |  
+| /<+-\
+| | | v
+\>+</ |
+  |   ^
+  \<->/"#;
+
+    let ticks: Vec<Tick> = World::parse(s)
+        .unwrap()
+        .ticks()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    let crashes: Vec<Crash> = ticks.iter().flat_map(|t| t.crashes.clone()).collect();
+
+    // carts are numbered in reading order, and the moving one comes first
+    assert_eq!(
+        vec![
+            Crash {
+                tick: 1,
+                x: 2,
+                y: 0,
+                carts: (1, 0)
+            },
+            Crash {
+                tick: 1,
+                x: 2,
+                y: 4,
+                carts: (5, 4)
+            },
+            Crash {
+                tick: 1,
+                x: 6,
+                y: 4,
+                carts: (6, 3)
+            },
+            Crash {
+                tick: 3,
+                x: 2,
+                y: 4,
+                carts: (7, 2)
+            },
+        ],
+        crashes
+    );
+
+    assert_eq!(3, ticks.len());
+    assert_eq!(vec![(2, 2, 2), (7, 2, 6), (8, 6, 6)], ticks[0].survivors);
+    assert_eq!(vec![(8, 6, 4)], ticks[2].survivors);
+    assert_eq!((6, 4), simulate2(s).unwrap());
+}
+
+#[test]
+fn test_validation() {
+    let err = |s: &str| match World::parse(s) {
+        Err(AocError::InvalidToken(msg)) => msg,
+        other => panic!("unexpected {:?}", other),
+    };
+
+    // a tiny loop, with curves only next to each other
+    assert!(World::parse("/\\\n\\/").is_ok());
+
+    assert_eq!(
+        "line 2, column 5: '|' is not connected to the track",
+        err("/->-\\\n|   |\n\\----")
+    );
+    assert_eq!(
+        "line 2, column 3: '/' is not connected to the track",
+        err("/->-\\\n| / |\n\\---/")
+    );
+    assert_eq!(
+        "line 1, column 3: unexpected '#'",
+        err("/-#-\\\n|   |\n\\---/")
+    );
+    // a cart on an intersection hides the vertical track
+    assert!(err("  |\n->>-\n  |").starts_with("line 1, column 3"));
+}
+
+#[test]
+fn test_infinite_loop() {
+    // two carts running after each other
+    let s = "/>->\\\n|   |\n\\---/";
+    let ticks: Vec<_> = World::parse(s).unwrap().ticks().collect();
+
+    assert_eq!(12, ticks.len());
+    match ticks.last() {
+        Some(Err(AocError::InfiniteLoop(msg))) => assert_eq!(
+            "the carts are at tick 12 in the same state as at tick 0",
+            msg
+        ),
+        other => panic!("unexpected {:?}", other),
+    }
+    assert!(simulate2(s).is_err());
+}
+
+#[test]
+fn test_frames() {
+    let s = std::fs::read_to_string("input/input13_debug.txt").expect("cannot read file");
+    let frames = frames(&s).unwrap();
+
+    assert_eq!(s.trim_end_matches('\n'), frames[0].trim_end_matches('\n'));
+    assert_eq!(
+        r#"/-->\        
+|   |  /----\
+| /-+--+-\  |
+| | |  | |  |
+\-+-/  \->--/
+  \------/   
+"#,
+        frames[1]
+    );
+    assert_eq!(
+        r#"/---\        
+|   |  /----\
+| /-+--v-\  |
+| | |  | |  |
+\-+-/  ^-+--/
+  \------/   
+"#,
+        frames[13]
+    );
+    assert_eq!(
+        r#"/---\        
+|   |  /----\
+| /-+--+-\  |
+| | |  X |  |
+\-+-/  \-+--/
+  \------/   
+"#,
+        frames[14]
+    );
+    assert_eq!(15, frames.len());
 }
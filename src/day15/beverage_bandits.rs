@@ -1,35 +1,181 @@
 use crate::common::error::AocError;
+use crate::common::image::Image;
 use crate::common::response::AocResponse;
 
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::fmt;
+use std::thread;
+use std::time::Duration;
+
+const ELF: char = 'E';
+const GOBLIN: char = 'G';
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 struct Unit {
     x: usize,
     y: usize,
-    elf: bool,
+    faction: char,
     hp: u32,
     ap: u32,
 }
 
 impl Unit {
-    fn new(x: usize, y: usize, elf: bool, hp: u32, ap: u32) -> Unit {
-        Unit { x, y, elf, hp, ap }
+    fn new(x: usize, y: usize, faction: char, hp: u32, ap: u32) -> Unit {
+        Unit {
+            x,
+            y,
+            faction,
+            hp,
+            ap,
+        }
+    }
+
+    fn is_elf(&self) -> bool {
+        self.faction == ELF
     }
 }
 
-#[derive(PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Stats {
+    hp: u32,
+    ap: u32,
+}
+
+// which adjacent enemy a unit attacks, ties being broken in reading order
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Targeting {
+    FewestHitPoints,
+    MostHitPoints,
+    ReadingOrder,
+}
+
+// the rules of the puzzle by default: any letter on the map is a unit of the faction of that
+// letter, with 200 HP and 3 attack power, moving and attacking only horizontally and vertically
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CombatRules {
+    default: Stats,
+    factions: HashMap<char, Stats>,
+    diagonal: bool,
+    targeting: Targeting,
+}
+
+impl Default for CombatRules {
+    fn default() -> Self {
+        CombatRules {
+            default: Stats { hp: 200, ap: 3 },
+            factions: HashMap::new(),
+            diagonal: false,
+            targeting: Targeting::FewestHitPoints,
+        }
+    }
+}
+
+impl CombatRules {
+    pub fn faction(mut self, faction: char, hp: u32, ap: u32) -> Self {
+        self.factions.insert(faction, Stats { hp, ap });
+        self
+    }
+
+    pub fn diagonal(mut self, diagonal: bool) -> Self {
+        self.diagonal = diagonal;
+        self
+    }
+
+    pub fn targeting(mut self, targeting: Targeting) -> Self {
+        self.targeting = targeting;
+        self
+    }
+
+    fn stats(&self, faction: char) -> Stats {
+        *self.factions.get(&faction).unwrap_or(&self.default)
+    }
+}
+
+#[derive(PartialEq, Eq, Clone)]
 struct State {
     walls: Vec<Vec<bool>>,
     units: Vec<Unit>,
+    // index in units of the unit standing on each node
+    occupancy: Vec<Vec<Option<usize>>>,
+    rules: CombatRules,
 }
 
 #[derive(Debug, PartialEq, Eq, Copy, Clone, Hash)]
 struct Node(usize, usize); // (x,y)
 
+#[derive(Debug, PartialEq, Eq, Clone)]
+enum Event {
+    Move {
+        from: Node,
+        to: Node,
+    },
+    Attack {
+        from: Node,
+        to: Node,
+        damage: u32,
+        hp: u32,
+    },
+    Death {
+        at: Node,
+        faction: char,
+    },
+    RoundEnd {
+        round: u32,
+    },
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Event::Move { from, to } => write!(
+                f,
+                "unit at {},{} moves to {},{}",
+                from.0, from.1, to.0, to.1
+            ),
+            Event::Attack {
+                from,
+                to,
+                damage,
+                hp,
+            } => write!(
+                f,
+                "unit at {},{} attacks {},{} for {} damage ({} HP left)",
+                from.0, from.1, to.0, to.1, damage, hp
+            ),
+            Event::Death { at, faction } => {
+                write!(f, "unit {} at {},{} dies", faction, at.0, at.1)
+            }
+            Event::RoundEnd { round } => write!(f, "end of round {}", round),
+        }
+    }
+}
+
+// the result of a fight for a given elf attack power, when stopped at the first elf death the
+// rounds, survivors and hp are the ones at that moment
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Outcome {
+    pub power: u32,
+    pub rounds: u32,
+    pub survivors: usize,
+    pub elf_losses: usize,
+    pub hp: u32,
+}
+
+// everything that happened during a fight, and optionally the map at the end of each round
+struct Recording {
+    events: Vec<Event>,
+    snapshots: Vec<String>,
+}
+
 impl State {
     fn parse(s: &str, ap_elf: u32, ap_gob: u32) -> State {
+        let rules = CombatRules::default()
+            .faction(ELF, 200, ap_elf)
+            .faction(GOBLIN, 200, ap_gob);
+        State::parse_with(s, rules)
+    }
+
+    fn parse_with(s: &str, rules: CombatRules) -> State {
         let lines = s.split('\n');
 
         let mut units = vec![];
@@ -40,13 +186,10 @@ impl State {
                 match case {
                     '#' => wall.push(true),
                     '.' => wall.push(false),
-                    'G' => {
+                    c if c.is_ascii_alphabetic() => {
                         wall.push(false);
-                        units.push(Unit::new(x, y, false, 200, ap_gob));
-                    }
-                    'E' => {
-                        wall.push(false);
-                        units.push(Unit::new(x, y, true, 200, ap_elf));
+                        let stats = rules.stats(c);
+                        units.push(Unit::new(x, y, c, stats.hp, stats.ap));
                     }
                     _ => panic!("Unexpected token"),
                 }
@@ -57,46 +200,82 @@ impl State {
         // sort units by turn order
         units.sort_by_key(|u| (u.y, u.x));
 
-        State { walls, units }
+        let occupancy = walls.iter().map(|w| vec![None; w.len()]).collect();
+        let mut state = State {
+            walls,
+            units,
+            occupancy,
+            rules,
+        };
+        state.index_units();
+        state
+    }
+
+    // to call whenever units are removed or reordered
+    fn index_units(&mut self) {
+        for row in self.occupancy.iter_mut() {
+            for case in row.iter_mut() {
+                *case = None;
+            }
+        }
+        for (i, u) in self.units.iter().enumerate() {
+            self.occupancy[u.y][u.x] = Some(i);
+        }
     }
 
     fn unit_at(&self, n: &Node) -> Option<&Unit> {
-        self.units.iter().find(|u| u.x == n.0 && u.y == n.1)
+        self.occupancy[n.1][n.0].map(|i| &self.units[i])
     }
 
     fn unit_at_mut(&mut self, n: &Node) -> &mut Unit {
-        self.units
-            .iter_mut()
-            .find(|u| u.x == n.0 && u.y == n.1)
-            .unwrap()
+        let i = self.occupancy[n.1][n.0].unwrap();
+        &mut self.units[i]
     }
 
-    fn around(n: Node) -> impl Iterator<Item = Node> {
-        [(-1isize, 0isize), (1, 0), (0, -1), (0, 1)]
+    fn around(&self, n: Node) -> impl Iterator<Item = Node> {
+        let directions: &'static [(isize, isize)] = if self.rules.diagonal {
+            &[
+                (-1, -1),
+                (0, -1),
+                (1, -1),
+                (-1, 0),
+                (1, 0),
+                (-1, 1),
+                (0, 1),
+                (1, 1),
+            ]
+        } else {
+            &[(-1, 0), (1, 0), (0, -1), (0, 1)]
+        };
+        directions
             .iter()
             .map(move |(dx, dy)| (n.0 as isize + dx, n.1 as isize + dy))
             .map(|(x, y)| Node(x as usize, y as usize))
     }
 
+    // number of factions with units still alive
+    fn factions(&self) -> usize {
+        self.units
+            .iter()
+            .map(|u| u.faction)
+            .collect::<HashSet<_>>()
+            .len()
+    }
+
     fn is_free(&self, n: &Node) -> bool {
-        !self.walls[n.1][n.0]
-            && self
-                .units
-                .iter()
-                .find(|u| u.x == n.0 && u.y == n.1)
-                .is_none()
+        !self.walls[n.1][n.0] && self.occupancy[n.1][n.0].is_none()
     }
 
     // all node from opposite side that are in range of the given one
     fn in_range(&self, n: &Node) -> Vec<Node> {
-        let is_elf = self.unit_at(n).unwrap().elf;
+        let faction = self.unit_at(n).unwrap().faction;
         let mut set = HashSet::new();
         for gob in self.units.iter() {
-            if gob.elf == is_elf {
+            if gob.faction == faction {
                 continue; // skip units in the same side
             }
 
-            set.extend(Self::around(Node(gob.x, gob.y)).filter(|n| self.is_free(n))); // i.e addAll(iterator)
+            set.extend(self.around(Node(gob.x, gob.y)).filter(|n| self.is_free(n))); // i.e addAll(iterator)
         }
 
         let mut vec: Vec<_> = set.into_iter().collect();
@@ -106,12 +285,33 @@ impl State {
         vec
     }
 
+    // breadth first search of the distance to all the free nodes reachable from the given one
+    fn distances(&self, from: &Node) -> Vec<Vec<Option<usize>>> {
+        let mut distances: Vec<Vec<Option<usize>>> =
+            self.walls.iter().map(|w| vec![None; w.len()]).collect();
+        distances[from.1][from.0] = Some(0);
+
+        let mut queue = VecDeque::new();
+        queue.push_back((*from, 0));
+        while let Some((n, d)) = queue.pop_front() {
+            for next in self.around(n) {
+                if distances[next.1][next.0].is_none() && self.is_free(&next) {
+                    distances[next.1][next.0] = Some(d + 1);
+                    queue.push_back((next, d + 1));
+                }
+            }
+        }
+
+        distances
+    }
+
     // all reachable nodes from the given one, ordered by distance, and then per units turn order
+    #[cfg(test)]
     fn reachables(&self, n: &Node) -> Vec<Node> {
         let reachables = pathfinding::directed::dijkstra::dijkstra_all(n, |n| {
             // cannot collect the iterator at any point here, as it will be collected by dijkstra_all
             // or else "temporary value moved while borrowing" error
-            Self::around(*n).filter(|n| self.is_free(n)).map(|n| (n, 1)) // cost of 1
+            self.around(*n).filter(|n| self.is_free(n)).map(|n| (n, 1)) // cost of 1
         });
 
         let mut reachables_nodes: Vec<_> = reachables.iter().map(|(k, v)| (*k, v.1)).collect();
@@ -124,8 +324,9 @@ impl State {
         reachables_nodes.iter().map(|e| e.0).collect()
     }
 
-    // use dijkstra_all to find the best node
-    fn find_target(&self, start: &Node) -> Option<Node> {
+    // use dijkstra_all to find the best node, the search replaced by find_target
+    #[cfg(test)]
+    fn find_target_dijkstra(&self, start: &Node) -> Option<Node> {
         let in_range = self.in_range(start);
         let reachables = self.reachables(start);
 
@@ -135,27 +336,22 @@ impl State {
             .cloned()
     }
 
+    #[cfg(test)]
     fn path_cost_to(&self, n: &Node, to_n: &Node) -> Option<usize> {
-        let shortest_path = pathfinding::directed::dijkstra::dijkstra(
+        pathfinding::directed::dijkstra::dijkstra(
             n,
-            |n| {
-                // cannot collect the iterator at any point here, as it will be collected by dijkstra_all
-                // or else "temporary value moved while borrowing" error
-                Self::around(*n).filter(|n| self.is_free(n)).map(|n| (n, 1)) // cost of 1
-            },
+            |n| self.around(*n).filter(|n| self.is_free(n)).map(|n| (n, 1)), // cost of 1
             |n| *n == *to_n,
-        );
-
-        if shortest_path.is_some() {
-            Some(shortest_path.unwrap().1)
-        } else {
-            None
-        }
+        )
+        .map(|(_, cost)| cost)
     }
 
-    fn find_move_toward(&self, start: &Node, target: &Node) -> Node {
+    // the search replaced by find_move_toward
+    #[cfg(test)]
+    fn find_move_toward_dijkstra(&self, start: &Node, target: &Node) -> Node {
         // what is the cost of all my neighbourds?
-        let moves_and_costs: Vec<_> = Self::around(*start)
+        let moves_and_costs: Vec<_> = self
+            .around(*start)
             .filter(|n| self.is_free(n))
             .filter_map(|n| self.path_cost_to(&n, target).map(|c| (n, c)))
             .collect();
@@ -163,17 +359,50 @@ impl State {
             .iter()
             .min_by_key(|(n, c)| (c, n.1, n.0))
             .unwrap();
-        (*next_move).0
+        next_move.0
     }
 
-    fn step_unit(&mut self, n: &mut Node) {
+    // the closest node in range of an enemy, in turn order in case of tie
+    fn find_target(&self, start: &Node) -> Option<Node> {
+        let distances = self.distances(start);
+
+        self.in_range(start)
+            .into_iter()
+            .filter_map(|n| distances[n.1][n.0].map(|d| (d, n.1, n.0)))
+            .min()
+            .map(|(_, y, x)| Node(x, y))
+    }
+
+    // the free node next to start on a shortest path to target, in turn order in case of tie
+    fn find_move_toward(&self, start: &Node, target: &Node) -> Node {
+        // the distance from my neighbours to the target is the distance from the target to them
+        let distances = self.distances(target);
+
+        self.around(*start)
+            .filter(|n| self.is_free(n))
+            .filter_map(|n| distances[n.1][n.0].map(|d| (d, n.1, n.0)))
+            .min()
+            .map(|(_, y, x)| Node(x, y))
+            .unwrap()
+    }
+
+    fn step_unit(&mut self, n: &mut Node, events: &mut Vec<Event>) {
         let me = self.unit_at(n).unwrap().clone();
         // if no enemy around me
-        if !Self::around(*n).any(|n| self.unit_at(&n).map(|u| u.elf != me.elf).unwrap_or(false)) {
+        if !self.around(*n).any(|n| {
+            self.unit_at(&n)
+                .map(|u| u.faction != me.faction)
+                .unwrap_or(false)
+        }) {
             if let Some(chosen) = self.find_target(n) {
-                //println!("{:?} moving to {:?}", n, chosen);
                 let next_node = self.find_move_toward(n, &chosen);
-                let mut unit = self.unit_at_mut(n);
+                events.push(Event::Move {
+                    from: *n,
+                    to: next_node,
+                });
+                let i = self.occupancy[n.1][n.0].take().unwrap();
+                self.occupancy[next_node.1][next_node.0] = Some(i);
+                let unit = &mut self.units[i];
                 unit.x = next_node.0;
                 unit.y = next_node.1;
                 n.0 = next_node.0;
@@ -181,32 +410,54 @@ impl State {
             }
         }
         // if enemy around me
-        if let Some(t) = Self::around(*n)
-            .filter_map(|n| self.unit_at(&n).filter(|u| u.elf != me.elf))
-            .min_by_key(|p| (p.hp, p.y, p.x))
+        let targeting = self.rules.targeting;
+        if let Some(t) = self
+            .around(*n)
+            .filter_map(|n| self.unit_at(&n).filter(|u| u.faction != me.faction))
+            .min_by_key(|p| {
+                let hp = i64::from(p.hp);
+                match targeting {
+                    Targeting::FewestHitPoints => (hp, p.y, p.x),
+                    Targeting::MostHitPoints => (-hp, p.y, p.x),
+                    Targeting::ReadingOrder => (0, p.y, p.x),
+                }
+            })
         {
-            //println!("{:?} attacking {:?}", n, t);
-            let target = self.unit_at_mut(&Node(t.x, t.y));
+            let at = Node(t.x, t.y);
+            let target = self.unit_at_mut(&at);
             // saturating_sub cap to 0 instead of overflowing
             target.hp = target.hp.saturating_sub(me.ap);
-            if target.hp == 0 {
+            let (hp, faction) = (target.hp, target.faction);
+            events.push(Event::Attack {
+                from: *n,
+                to: at,
+                damage: me.ap,
+                hp,
+            });
+            if hp == 0 {
+                events.push(Event::Death { at, faction });
                 self.units.retain(|u| u.hp > 0);
+                self.index_units();
             }
         }
-        //println!("state: {:?}", self);
     }
 
     fn step(&mut self) {
+        self.step_recorded(&mut vec![]);
+    }
+
+    fn step_recorded(&mut self, events: &mut Vec<Event>) {
         let units_at_start = self.units.clone();
         for unit in units_at_start {
             let mut unit_node = Node(unit.x, unit.y);
             if self.unit_at(&unit_node).is_none() {
                 continue; //not a unit, skip it
             }
-            self.step_unit(&mut unit_node);
+            self.step_unit(&mut unit_node, events);
         }
         // sort again the units
         self.units.sort_by_key(|u| (u.y, u.x));
+        self.index_units();
     }
 
     // return the number of rounds
@@ -214,13 +465,65 @@ impl State {
         let mut r = 0;
         loop {
             self.step();
-            if self.units.iter().all(|u| u.elf) || self.units.iter().all(|u| !u.elf) {
+            if self.factions() <= 1 {
                 return r;
             }
             r += 1;
         }
     }
 
+    fn with_elf_power(&self, ap: u32) -> State {
+        let mut state = self.clone();
+        for u in state.units.iter_mut().filter(|u| u.is_elf()) {
+            u.ap = ap;
+        }
+        state
+    }
+
+    fn elves(&self) -> usize {
+        self.units.iter().filter(|u| u.is_elf()).count()
+    }
+
+    // fight until one side wins, or until the first elf death if abort_on_elf_death
+    fn trial(mut self, abort_on_elf_death: bool) -> Outcome {
+        let power = self.units.iter().find(|u| u.is_elf()).map_or(0, |u| u.ap);
+        let elves = self.elves();
+        let mut rounds = 0;
+        loop {
+            self.step();
+            let elf_losses = elves - self.elves();
+            if self.factions() <= 1 || (abort_on_elf_death && elf_losses > 0) {
+                return Outcome {
+                    power,
+                    rounds,
+                    survivors: self.units.len(),
+                    elf_losses,
+                    hp: self.remaining_hp(),
+                };
+            }
+            rounds += 1;
+        }
+    }
+
+    // same as fight_to_death, but keeping track of what happened
+    fn fight_recorded(&mut self, snapshots: bool) -> Recording {
+        let mut recording = Recording {
+            events: vec![],
+            snapshots: vec![],
+        };
+        for round in 1.. {
+            self.step_recorded(&mut recording.events);
+            recording.events.push(Event::RoundEnd { round });
+            if snapshots {
+                recording.snapshots.push(self.to_string());
+            }
+            if self.factions() <= 1 {
+                break;
+            }
+        }
+        recording
+    }
+
     fn remaining_hp(&self) -> u32 {
         self.units.iter().map(|u| u.hp).sum()
     }
@@ -234,18 +537,38 @@ impl fmt::Debug for State {
             for (x, case) in wall.iter().enumerate() {
                 let unit = &self.units.iter().find(|u| u.x == x && u.y == y);
                 let char = match (unit, case) {
+                    (None, true) => '#',
+                    (None, false) => '.',
+                    (Some(u), _) => u.faction,
+                };
+                write!(f, "{}", char)?;
+            }
+            writeln!(f)?;
+        }
+
+        Ok(())
+    }
+}
+
+// the map as the puzzle displays it, with the HP of the units of each line on the right
+impl fmt::Display for State {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (y, wall) in self.walls.iter().enumerate() {
+            let mut hps = vec![];
+            for (x, case) in wall.iter().enumerate() {
+                let char = match (self.unit_at(&Node(x, y)), case) {
                     (None, true) => '#',
                     (None, false) => '.',
                     (Some(u), _) => {
-                        if u.elf {
-                            'E'
-                        } else {
-                            'G'
-                        }
+                        hps.push(format!("{}({})", u.faction, u.hp));
+                        u.faction
                     }
                 };
                 write!(f, "{}", char)?;
             }
+            if !hps.is_empty() {
+                write!(f, "   {}", hps.join(", "))?;
+            }
             writeln!(f)?;
         }
 
@@ -253,6 +576,89 @@ impl fmt::Debug for State {
     }
 }
 
+impl State {
+    fn image(&self) -> Image {
+        let mut image = Image::from_grid(&self.walls, |&wall| {
+            if wall {
+                [60, 60, 60]
+            } else {
+                [200, 200, 180]
+            }
+        });
+        for u in &self.units {
+            let colour = match u.faction {
+                ELF => [40, 170, 60],
+                GOBLIN => [200, 40, 40],
+                _ => [220, 180, 0],
+            };
+            image.set(u.x, u.y, colour);
+        }
+        image
+    }
+}
+
+fn colorize(snapshot: &str) -> String {
+    snapshot
+        .replace('E', "\x1b[32mE\x1b[0m")
+        .replace('G', "\x1b[31mG\x1b[0m")
+}
+
+// play back a fight in the terminal, one round every `delay_ms` milliseconds
+pub fn replay(filename: &str, delay_ms: u64) -> Result<(), AocError> {
+    let s = std::fs::read_to_string(filename)?;
+    let mut state = State::parse(&s, 3, 3);
+
+    let initial = state.to_string();
+    let recording = state.fight_recorded(true);
+
+    let mut events = recording.events.iter();
+    let snapshots = std::iter::once(&initial).chain(recording.snapshots.iter());
+    for (round, snapshot) in snapshots.enumerate() {
+        // clear the terminal and move the cursor to the top left corner
+        print!("\x1b[2J\x1b[H");
+        if round == 0 {
+            println!("Initially:");
+        } else {
+            println!(
+                "After {} round{}:",
+                round,
+                if round == 1 { "" } else { "s" }
+            );
+        }
+        println!("{}", colorize(snapshot));
+        if round > 0 {
+            for event in events.by_ref() {
+                if let Event::RoundEnd { .. } = event {
+                    break;
+                }
+                println!("{}", event);
+            }
+        }
+        thread::sleep(Duration::from_millis(delay_ms));
+    }
+
+    Ok(())
+}
+
+// save the map at the end of the combat as a .png or .ppm image
+pub fn write_image(filename: &str, output: &str) -> Result<(), AocError> {
+    let s = std::fs::read_to_string(filename)?;
+    let mut state = State::parse(&s, 3, 3);
+
+    state.fight_to_death();
+    state.image().scale(8)?.save(output)
+}
+
+// fight with custom rules, return the number of full rounds, the HP left and the winning faction
+pub fn fight(s: &str, rules: CombatRules) -> (u32, u32, Option<char>) {
+    let mut state = State::parse_with(s, rules);
+
+    let rounds = state.fight_to_death();
+    let winner = state.units.first().map(|u| u.faction);
+
+    (rounds, state.remaining_hp(), winner)
+}
+
 fn combat1(s: &str) -> (u32, u32) {
     let mut state = State::parse(s, 3, 3);
 
@@ -262,24 +668,33 @@ fn combat1(s: &str) -> (u32, u32) {
     (rounds, hps)
 }
 
-fn combat2(s: &str) -> (u32, u32) {
-    'outer: for e in 3.. {
-        let mut s = State::parse(s, e, 3);
-        let elves = s.units.iter().filter(|u| u.elf).count();
-        let mut i = 0;
-        loop {
-            s.step();
-            if s.units.iter().filter(|u| u.elf).count() < elves {
-                continue 'outer;
-            }
-            if s.units.len() == elves {
-                let remain = s.units.iter().map(|u| u.hp).sum::<u32>();
-                return (i, remain);
-            }
-            i += 1;
+// the first fight without elf losses, and the outcome of every fight tried to find it, one per
+// elf attack power from 3 upwards
+// trials are run in parallel in batches of increasing attack powers, each one stopping at the
+// first elf death, so that no assumption is made on the outcome being monotonic with the power
+pub fn minimum_elf_power(s: &str) -> (Outcome, Vec<Outcome>) {
+    let state = State::parse(s, 3, 3);
+    let batch = thread::available_parallelism().map_or(1, |n| n.get()) as u32;
+
+    let mut table = vec![];
+    let mut first = 3;
+    loop {
+        let outcomes: Vec<Outcome> = thread::scope(|scope| {
+            let trials: Vec<_> = (first..first + batch)
+                .map(|power| {
+                    let state = &state;
+                    scope.spawn(move || state.with_elf_power(power).trial(true))
+                })
+                .collect();
+            trials.into_iter().map(|t| t.join().unwrap()).collect()
+        });
+        table.extend(outcomes);
+
+        if let Some(win) = table.iter().find(|o| o.elf_losses == 0) {
+            return (win.clone(), table);
         }
+        first += batch;
     }
-    panic!()
 }
 
 pub fn answer1() -> Result<AocResponse<u32>, AocError> {
@@ -297,12 +712,12 @@ pub fn answer1() -> Result<AocResponse<u32>, AocError> {
 pub fn answer2() -> Result<AocResponse<u32>, AocError> {
     let s = std::fs::read_to_string("input/input15.txt").expect("cannot read file");
 
-    let result = combat2(&s);
+    let (result, _) = minimum_elf_power(&s);
     Ok(AocResponse::new(
         15,
         2,
         "Beverage Bandits",
-        result.0 * result.1,
+        result.rounds * result.hp,
     ))
 }
 
@@ -582,6 +997,40 @@ fn test_combat_1() {
     assert_eq!(state.remaining_hp(), 590);
 }
 
+#[test]
+fn test_pathing() {
+    let mut state = State::parse(
+        r#"
+#########
+#G......#
+#.E.#...#
+#..##..G#
+#...##..#
+#...#...#
+#.G...G.#
+#.....G.#
+#########"#,
+        3,
+        3,
+    );
+
+    // the breadth first searches choose the same moves as the original Dijkstra ones
+    while state.factions() > 1 {
+        for unit in &state.units {
+            let n = Node(unit.x, unit.y);
+            let target = state.find_target(&n);
+            assert_eq!(state.find_target_dijkstra(&n), target);
+            if let Some(target) = target {
+                assert_eq!(
+                    state.find_move_toward_dijkstra(&n, &target),
+                    state.find_move_toward(&n, &target)
+                );
+            }
+        }
+        state.step();
+    }
+}
+
 #[test]
 fn test_combat_2() {
     let mut state = State::parse(
@@ -600,3 +1049,182 @@ fn test_combat_2() {
     assert_eq!(state.fight_to_death(), 46);
     assert_eq!(state.remaining_hp(), 859);
 }
+
+#[test]
+fn test_recording() {
+    let mut state = State::parse(
+        r#"
+#######
+#.G...#
+#...EG#
+#.#.#G#
+#..G#E#
+#.....#
+#######"#,
+        3,
+        3,
+    );
+
+    let recording = state.fight_recorded(true);
+
+    assert_eq!(
+        r#"#######
+#..G..#   G(200)
+#...EG#   E(197), G(197)
+#.#G#G#   G(200), G(197)
+#...#E#   E(197)
+#.....#
+#######
+"#,
+        recording.snapshots[0]
+    );
+    assert_eq!(
+        r#"#######
+#G....#   G(200)
+#.G...#   G(131)
+#.#.#G#   G(59)
+#...#.#
+#....G#   G(200)
+#######
+"#,
+        recording.snapshots[46]
+    );
+
+    assert_eq!(
+        Event::Move {
+            from: Node(2, 1),
+            to: Node(3, 1)
+        },
+        recording.events[0]
+    );
+    let deaths = recording
+        .events
+        .iter()
+        .filter(|e| match e {
+            Event::Death { faction, .. } => *faction == ELF,
+            _ => false,
+        })
+        .count();
+    assert_eq!(2, deaths);
+    assert_eq!(
+        Some(&Event::RoundEnd { round: 47 }),
+        recording.events.last()
+    );
+}
+
+#[test]
+fn test_minimum_elf_power() {
+    let (result, table) = minimum_elf_power(
+        r#"
+#######
+#.G...#
+#...EG#
+#.#.#G#
+#..G#E#
+#.....#
+#######"#,
+    );
+
+    assert_eq!(15, result.power);
+    assert_eq!(29, result.rounds);
+    assert_eq!(172, result.hp);
+    assert_eq!(0, result.elf_losses);
+
+    let losing: Vec<_> = table.iter().filter(|o| o.power < 15).collect();
+    assert_eq!(12, losing.len());
+    assert!(losing.iter().all(|o| o.elf_losses > 0));
+
+    // one outcome per power, from 3 up to at least the winning one
+    assert!(table.iter().zip(3..).all(|(o, power)| o.power == power));
+    assert_eq!(
+        Outcome {
+            power: 3,
+            rounds: 22,
+            survivors: 5,
+            elf_losses: 1,
+            hp: 793
+        },
+        table[0]
+    );
+    assert_eq!(
+        Outcome {
+            power: 12,
+            rounds: 26,
+            survivors: 2,
+            elf_losses: 1,
+            hp: 349
+        },
+        table[9]
+    );
+    assert_eq!(Some(&result), table.iter().find(|o| o.power == 15));
+}
+
+#[test]
+fn test_rules_factions() {
+    let s = r#"
+#######
+#E...O#
+#.....#
+#G....#
+#######"#;
+    let rules = CombatRules::default()
+        .faction('E', 50, 3)
+        .faction('O', 300, 10);
+
+    let state = State::parse_with(s, rules.clone());
+    assert_eq!(3, state.factions());
+    assert_eq!(50, state.unit_at(&Node(1, 1)).unwrap().hp);
+    assert_eq!(200, state.unit_at(&Node(1, 3)).unwrap().hp);
+    assert_eq!(300, state.unit_at(&Node(5, 1)).unwrap().hp);
+
+    let (_, _, winner) = fight(s, rules);
+    assert_eq!(Some('O'), winner);
+}
+
+#[test]
+fn test_rules_diagonal() {
+    let mut state = State::parse_with(
+        r#"
+#####
+#E..#
+#...#
+#..G#
+#####"#,
+        CombatRules::default().diagonal(true),
+    );
+
+    // the elf moves diagonally next to the goblin, and both attack across the diagonal
+    state.step();
+    assert_eq!(197, state.unit_at(&Node(2, 2)).unwrap().hp);
+    assert_eq!(197, state.unit_at(&Node(3, 3)).unwrap().hp);
+}
+
+#[test]
+fn test_rules_targeting() {
+    let s = r#"
+#####
+#.G.#
+#GEG#
+#####"#;
+
+    let mut fewest = State::parse(s, 3, 3);
+    fewest.unit_at_mut(&Node(1, 2)).hp = 10;
+    let mut most = fewest.clone();
+    most.rules = most.rules.targeting(Targeting::MostHitPoints);
+    most.unit_at_mut(&Node(3, 2)).hp = 250;
+    let mut reading = fewest.clone();
+    reading.rules = reading.rules.targeting(Targeting::ReadingOrder);
+
+    let attacked = |mut state: State| {
+        let mut events = vec![];
+        state.step_unit(&mut Node(2, 2), &mut events);
+        match events[0] {
+            Event::Attack { to, .. } => to,
+            _ => panic!("expected an attack"),
+        }
+    };
+
+    assert_eq!(Node(1, 2), attacked(fewest));
+    assert_eq!(Node(3, 2), attacked(most));
+    assert_eq!(Node(2, 1), attacked(reading));
+}
@@ -1,72 +1,9 @@
+use crate::common::elfcode::{InstructionSet, Opcode};
 use crate::common::error::AocError;
 use crate::common::response::AocResponse;
 
 use std::collections::HashMap;
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq)]
-enum Opcode {
-    Addr,
-    Addi,
-    Mulr,
-    Muli,
-    Banr,
-    Bani,
-    Borr,
-    Bori,
-    Setr,
-    Seti,
-    Gtir,
-    Gtri,
-    Gtrr,
-    Eqir,
-    Eqri,
-    Eqrr,
-}
-
-impl Opcode {
-    fn all() -> &'static [Opcode] {
-        &[
-            Opcode::Addr,
-            Opcode::Addi,
-            Opcode::Mulr,
-            Opcode::Muli,
-            Opcode::Banr,
-            Opcode::Bani,
-            Opcode::Borr,
-            Opcode::Bori,
-            Opcode::Setr,
-            Opcode::Seti,
-            Opcode::Gtir,
-            Opcode::Gtri,
-            Opcode::Gtrr,
-            Opcode::Eqir,
-            Opcode::Eqri,
-            Opcode::Eqrr,
-        ]
-    }
-
-    fn apply(self, i: &[usize], r: &mut [usize]) {
-        match self {
-            Opcode::Addr => r[i[2]] = r[i[0]] + r[i[1]],
-            Opcode::Addi => r[i[2]] = r[i[0]] + i[1],
-            Opcode::Mulr => r[i[2]] = r[i[0]] * r[i[1]],
-            Opcode::Muli => r[i[2]] = r[i[0]] * i[1],
-            Opcode::Banr => r[i[2]] = r[i[0]] & r[i[1]],
-            Opcode::Bani => r[i[2]] = r[i[0]] & i[1],
-            Opcode::Borr => r[i[2]] = r[i[0]] | r[i[1]],
-            Opcode::Bori => r[i[2]] = r[i[0]] | i[1],
-            Opcode::Setr => r[i[2]] = r[i[0]],
-            Opcode::Seti => r[i[2]] = i[0],
-            Opcode::Gtir => r[i[2]] = if i[0] > r[i[1]] { 1 } else { 0 },
-            Opcode::Gtri => r[i[2]] = if r[i[0]] > i[1] { 1 } else { 0 },
-            Opcode::Gtrr => r[i[2]] = if r[i[0]] > r[i[1]] { 1 } else { 0 },
-            Opcode::Eqir => r[i[2]] = if i[0] == r[i[1]] { 1 } else { 0 },
-            Opcode::Eqri => r[i[2]] = if r[i[0]] == i[1] { 1 } else { 0 },
-            Opcode::Eqrr => r[i[2]] = if r[i[0]] == r[i[1]] { 1 } else { 0 },
-        }
-    }
-}
-
 struct Sample {
     before: [usize; 4],
     input: [usize; 4],
@@ -138,7 +75,7 @@ pub fn answer1() -> Result<AocResponse<i32>, AocError> {
 
     let mut triple_candidates = 0;
     for sample in samples.iter() {
-        let candidates = sample.probe_ops(Opcode::all().to_vec().iter().cloned());
+        let candidates = sample.probe_ops(InstructionSet::standard().ops().iter().cloned());
         if candidates.len() >= 3 {
             triple_candidates += 1;
         }
@@ -156,7 +93,7 @@ pub fn answer2() -> Result<AocResponse<usize>, AocError> {
     let s = std::fs::read_to_string("input/input16_q1.txt")?;
     let samples = Sample::parse(&s);
 
-    let mut to_map = Opcode::all().to_vec();
+    let mut to_map = InstructionSet::standard().ops().to_vec();
     let mut mapped: HashMap<usize, Opcode> = HashMap::new();
     for sample in samples.iter().cycle() {
         if mapped.contains_key(&sample.opcode()) {
@@ -1,13 +1,13 @@
 use crate::common::error::AocError;
+use crate::common::image::Image;
 use crate::common::response::AocResponse;
 
 use self::State::*;
 use lazy_static::lazy_static;
 use regex::Regex;
 use std::cmp::{max, min};
+use std::collections::VecDeque;
 use std::fmt;
-use std::fs::File;
-use std::io::{BufRead, BufReader};
 use std::str::FromStr;
 
 #[derive(Debug)]
@@ -53,13 +53,81 @@ impl FromStr for Vein {
     }
 }
 
+// the clay veins, and the springs the water comes from
+#[derive(Debug)]
+struct Scan {
+    veins: Vec<Vein>,
+    springs: Vec<(usize, usize)>,
+}
+
+impl FromStr for Scan {
+    type Err = AocError;
+
+    // one vein per line, or a spring such as `spring x=500, y=0`. Without any spring, the water
+    // comes from x=500, y=0. A spring needs a column on its left for the water to flow in.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        lazy_static! {
+            static ref RE: Regex = Regex::new(r"^spring x=(\d+), y=(\d+)$").unwrap();
+        }
+
+        let mut veins = vec![];
+        let mut springs = vec![];
+        for line in s.lines().map(str::trim).filter(|l| !l.is_empty()) {
+            if let Some(c) = RE.captures(line) {
+                let x: usize = c[1].parse()?;
+                if x == 0 {
+                    return Err(format!("{:?}: a spring cannot be at x=0", line))?;
+                }
+                springs.push((x, c[2].parse()?));
+            } else {
+                veins.push(Vein::from_str(line)?);
+            }
+        }
+        if springs.is_empty() {
+            springs.push(SPRING);
+        }
+
+        Ok(Scan { veins, springs })
+    }
+}
+
+const SPRING: (usize, usize) = (500, 0);
+
+#[cfg(test)]
 #[derive(Debug, Clone, Copy)]
 enum Flow {
     Down,
     Side,
 }
 
-#[derive(Clone, Copy, PartialEq, Eq)]
+// a pending step of the water flow, the explicit counterpart of a call to `Ground::fill`
+#[derive(Debug, Clone, Copy)]
+enum Task {
+    Down {
+        x: usize,
+        y: usize,
+    },
+    // spread sideways from x, starting with direction `dir` (0 for left, 1 for right), with
+    // the walls found so far
+    Side {
+        x: usize,
+        y: usize,
+        dir: usize,
+        limit: [usize; 2],
+    },
+}
+
+// a body of still water, filled up to the row of its surface
+#[derive(Debug, PartialEq, Eq)]
+struct Basin {
+    still: u32,
+    // the highest row of still water, walls may hold parts of the basin lower
+    surface: usize,
+    // where the water flowing over the basin falls from, empty if it never overflows
+    spills: Vec<(usize, usize)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum State {
     Sand,
     Clay,
@@ -67,8 +135,10 @@ enum State {
     Flow,
 }
 
+#[derive(Clone)]
 struct Ground {
     field: Vec<Vec<State>>,
+    springs: Vec<(usize, usize)>,
     x_min: usize,
     x_max: usize,
     y_min: usize,
@@ -76,9 +146,25 @@ struct Ground {
 }
 
 impl Ground {
+    #[cfg(test)]
     fn from_veins(veins: &[Vein]) -> Ground {
-        let x_min = veins.iter().map(|v| min(v.x_start, v.x_end)).min().unwrap() as usize;
-        let x_max = veins.iter().map(|v| max(v.x_start, v.x_end)).max().unwrap() as usize;
+        Ground::with_springs(veins, &[SPRING])
+    }
+
+    fn with_springs(veins: &[Vein], springs: &[(usize, usize)]) -> Ground {
+        // water falling from a spring away from the clay still counts
+        let x_min = veins
+            .iter()
+            .map(|v| min(v.x_start, v.x_end) as usize)
+            .chain(springs.iter().map(|s| s.0))
+            .min()
+            .unwrap();
+        let x_max = veins
+            .iter()
+            .map(|v| max(v.x_start, v.x_end) as usize)
+            .chain(springs.iter().map(|s| s.0))
+            .max()
+            .unwrap();
         let y_min = veins.iter().map(|v| min(v.y_start, v.y_end)).min().unwrap() as usize;
         let y_max = veins.iter().map(|v| max(v.y_start, v.y_end)).max().unwrap() as usize;
 
@@ -93,6 +179,7 @@ impl Ground {
 
         Ground {
             field,
+            springs: springs.to_vec(),
             x_min,
             x_max,
             y_min,
@@ -101,9 +188,9 @@ impl Ground {
     }
 
     // Recursively fill the grid in given direction, starting at given point.
+    // Kept as the reference for `flow`, the recursion is as deep as the reservoirs
+    #[cfg(test)]
     fn fill(&mut self, x: usize, y: usize, flow: Flow) {
-        //println!("fill: x={}, y={}, flow={:?}", x, y, flow);
-        //println!("{}", self);
         match flow {
             Flow::Down => {
                 for dy in 1.. {
@@ -154,6 +241,205 @@ impl Ground {
         }
     }
 
+    // Fill the grid from a spring at the given point, with the same steps as `fill` but a stack
+    // of pending tasks instead of recursive calls.
+    fn flow(&mut self, x: usize, y: usize) {
+        let mut tasks = vec![Task::Down { x, y }];
+
+        while let Some(task) = tasks.pop() {
+            match task {
+                Task::Down { x, y } => {
+                    for dy in 1.. {
+                        if y + dy > self.y_max {
+                            break; // stop at the bottom of the grid
+                        }
+                        match self.field[y + dy][x] {
+                            Sand => self.field[y + dy][x] = Flow,
+                            Flow => break,
+                            Clay | Still => {
+                                tasks.push(Task::Side {
+                                    x,
+                                    y: y + dy - 1,
+                                    dir: 0,
+                                    limit: [0, 0],
+                                });
+                                break;
+                            }
+                        }
+                    }
+                }
+                Task::Side {
+                    x,
+                    y,
+                    dir,
+                    mut limit,
+                } => {
+                    if dir == 0 {
+                        self.field[y][x] = Flow;
+                    }
+                    // set when the water falls, the spreading resumes once the fall is done
+                    let mut falling = None;
+                    for (dir, limit_dir) in limit.iter_mut().enumerate().skip(dir) {
+                        for dx in 1.. {
+                            let new_x = if dir == 0 { x - dx } else { x + dx };
+                            match self.field[y][new_x] {
+                                Clay | Still => {
+                                    *limit_dir = new_x;
+                                    break;
+                                }
+                                Flow | Sand => {
+                                    self.field[y][new_x] = Flow;
+                                    if self.field[y + 1][new_x] == Sand {
+                                        falling = Some((dir, new_x));
+                                        break;
+                                    }
+                                }
+                            }
+                        }
+                        if falling.is_some() {
+                            break;
+                        }
+                    }
+
+                    if let Some((dir, new_x)) = falling {
+                        tasks.push(Task::Side {
+                            x,
+                            y,
+                            dir: dir + 1,
+                            limit,
+                        });
+                        tasks.push(Task::Down { x: new_x, y });
+                    } else if limit[0] > 0 && limit[1] > 0 {
+                        // if we reached both side, make it still, and flow from the level above
+                        for cur_x in limit[0] + 1..limit[1] {
+                            self.field[y][cur_x] = Still;
+                        }
+                        tasks.push(Task::Side {
+                            x,
+                            y: y - 1,
+                            dir: 0,
+                            limit: [0, 0],
+                        });
+                    }
+                }
+            }
+        }
+    }
+
+    // Fill the grid from all the springs. A spring falling on the water of another one either
+    // stops on its flow or spreads on its still water, as a single spring does on its own water
+    fn flow_all(&mut self) {
+        for (x, y) in self.springs.clone() {
+            self.flow(x, y);
+        }
+    }
+
+    // number of tiles the water of each spring reaches, as if it were the only one
+    fn reachable(&self) -> Vec<((usize, usize), u32)> {
+        self.springs
+            .iter()
+            .map(|&(x, y)| {
+                let mut ground = self.clone();
+                for row in ground.field.iter_mut() {
+                    for cell in row.iter_mut().filter(|c| **c != Clay) {
+                        *cell = Sand;
+                    }
+                }
+                ground.flow(x, y);
+                let (still, flow) = ground.count_water();
+                ((x, y), still + flow)
+            })
+            .collect()
+    }
+
+    // the bodies of still water, in reading order of their first tile. Buckets nested in a
+    // bigger one are part of its basin once it is filled above them
+    fn basins(&self) -> Vec<Basin> {
+        let mut seen = vec![vec![false; self.field[0].len()]; self.field.len()];
+        let mut basins = vec![];
+
+        for y in 0..=self.y_max {
+            for x in 0..self.field[y].len() {
+                if self.field[y][x] != Still || seen[y][x] {
+                    continue;
+                }
+
+                let mut still = 0;
+                let mut surface = y;
+                // the tiles under flowing water, on every side of the walls within the basin
+                let mut wet: Vec<(usize, usize)> = vec![];
+                let mut queue = VecDeque::new();
+                queue.push_back((x, y));
+                seen[y][x] = true;
+                while let Some((x, y)) = queue.pop_front() {
+                    still += 1;
+                    surface = min(surface, y);
+                    if self.field[y - 1][x] == Flow {
+                        wet.push((x, y));
+                    }
+                    for &(nx, ny) in &[(x - 1, y), (x + 1, y), (x, y - 1), (x, y + 1)] {
+                        if self.field[ny][nx] == Still && !seen[ny][nx] {
+                            seen[ny][nx] = true;
+                            queue.push_back((nx, ny));
+                        }
+                    }
+                }
+
+                // the water flowing over the basin, on both sides
+                let mut spills = vec![];
+                for &(x, y) in &wet {
+                    for &dir in &[-1isize, 1] {
+                        if let Some(spill) = self.spill(x, y - 1, dir) {
+                            if !spills.contains(&spill) {
+                                spills.push(spill);
+                            }
+                        }
+                    }
+                }
+                spills.sort();
+
+                basins.push(Basin {
+                    still,
+                    surface,
+                    spills,
+                });
+            }
+        }
+
+        basins
+    }
+
+    // follow the flowing water from (x, y) in the given direction, as long as it is held by clay
+    // or still water, and return where it falls
+    fn spill(&self, mut x: usize, y: usize, dir: isize) -> Option<(usize, usize)> {
+        while self.field[y][x] == Flow {
+            if !matches!(self.field[y + 1][x], Clay | Still) {
+                return Some((x, y));
+            }
+            x = (x as isize + dir) as usize;
+        }
+        None
+    }
+
+    // the area displayed as text, one pixel per tile
+    fn image(&self) -> Image {
+        let rows: Vec<Vec<State>> = self
+            .field
+            .iter()
+            .map(|row| row[self.x_min - 1..=self.x_max + 1].to_vec())
+            .collect();
+        let mut image = Image::from_grid(&rows, |state| match state {
+            Sand => [222, 196, 145],
+            Clay => [120, 72, 40],
+            Still => [30, 80, 200],
+            Flow => [120, 180, 255],
+        });
+        for &(x, y) in &self.springs {
+            image.set(x + 1 - self.x_min, y, [220, 20, 20]);
+        }
+        image
+    }
+
     fn count_water(&self) -> (u32, u32) {
         let mut still_count = 0;
         let mut flow_count = 0;
@@ -187,7 +473,11 @@ impl fmt::Display for Ground {
         writeln!(f, "Ground:")?;
         for y in 0..=self.y_max {
             for x in self.x_min - 1..=self.x_max + 1 {
-                write!(f, "{}", self.field[y][x])?;
+                if self.springs.contains(&(x, y)) {
+                    write!(f, "+")?;
+                } else {
+                    write!(f, "{}", self.field[y][x])?;
+                }
             }
             writeln!(f)?;
         }
@@ -196,26 +486,58 @@ impl fmt::Display for Ground {
     }
 }
 
-fn read_file(filename: &str) -> Result<Vec<Vein>, AocError> {
-    let file = File::open(filename)?;
-    let reader = BufReader::new(file);
+fn read_file(filename: &str) -> Result<Scan, AocError> {
+    let s = std::fs::read_to_string(filename)?;
+    Scan::from_str(&s)
+}
 
-    let mut file_lines = vec![];
-    for line in reader.lines() {
-        let line = line?;
-        let line = Vein::from_str(&line)?;
-        file_lines.push(line);
+// print the water of all the springs together, the tiles each one reaches on its own, and the
+// basins filled with still water
+pub fn print_springs(filename: &str) -> Result<(), AocError> {
+    let scan = read_file(filename)?;
+    let mut ground = Ground::with_springs(&scan.veins, &scan.springs);
+    ground.flow_all();
+
+    let (still, flow) = ground.count_water();
+    println!("still water: {}, flowing water: {}", still, flow);
+    for ((x, y), count) in ground.reachable() {
+        println!("spring x={}, y={}: {} tiles", x, y, count);
+    }
+    for basin in ground.basins() {
+        let spills: Vec<String> = basin
+            .spills
+            .iter()
+            .map(|(x, y)| format!("x={}, y={}", x, y))
+            .collect();
+        println!(
+            "basin at y={}: {} tiles of still water, {}",
+            basin.surface,
+            basin.still,
+            if spills.is_empty() {
+                "no overflow".to_string()
+            } else {
+                format!("overflows at {}", spills.join(" and "))
+            }
+        );
     }
 
-    Ok(file_lines)
+    Ok(())
+}
+
+// save the ground once filled with water as a .png or .ppm image
+pub fn write_image(filename: &str, output: &str) -> Result<(), AocError> {
+    let scan = read_file(filename)?;
+    let mut ground = Ground::with_springs(&scan.veins, &scan.springs);
+    ground.flow_all();
+
+    ground.image().scale(2)?.save(output)
 }
 
 pub fn answer1() -> Result<AocResponse<u32>, AocError> {
-    let veins = read_file("input/input17.txt")?;
+    let scan = read_file("input/input17.txt")?;
 
-    let mut ground = Ground::from_veins(&veins);
-    ground.fill(500, 0, Flow::Down);
-    //println!("{}", ground);
+    let mut ground = Ground::with_springs(&scan.veins, &scan.springs);
+    ground.flow_all();
 
     let count = ground.count_water();
 
@@ -228,10 +550,10 @@ pub fn answer1() -> Result<AocResponse<u32>, AocError> {
 }
 
 pub fn answer2() -> Result<AocResponse<u32>, AocError> {
-    let veins = read_file("input/input17.txt")?;
+    let scan = read_file("input/input17.txt")?;
 
-    let mut ground = Ground::from_veins(&veins);
-    ground.fill(500, 0, Flow::Down);
+    let mut ground = Ground::with_springs(&scan.veins, &scan.springs);
+    ground.flow_all();
 
     let count = ground.count_water();
     Ok(AocResponse::new(17, 2, "Reservoir Research", count.0))
@@ -239,7 +561,7 @@ pub fn answer2() -> Result<AocResponse<u32>, AocError> {
 
 #[test]
 fn test_parse() {
-    let veins = read_file("input/input17_debug.txt").unwrap();
+    let veins = read_file("input/input17_debug.txt").unwrap().veins;
 
     let field = Ground::from_veins(&veins);
 
@@ -252,7 +574,7 @@ fn test_parse() {
 
 #[test]
 fn test_fill() {
-    let veins = read_file("input/input17_debug.txt").unwrap();
+    let veins = read_file("input/input17_debug.txt").unwrap().veins;
 
     let mut ground = Ground::from_veins(&veins);
     println!("{}", ground);
@@ -261,3 +583,199 @@ fn test_fill() {
     println!("{}", ground);
     assert_eq!((29, 28), ground.count_water());
 }
+
+#[test]
+fn test_flow() {
+    for input in &["input/input17_debug.txt", "input/input17.txt"] {
+        let veins = read_file(input).unwrap().veins;
+
+        let mut recursive = Ground::from_veins(&veins);
+        recursive.fill(500, 0, Flow::Down);
+        let mut iterative = Ground::from_veins(&veins);
+        iterative.flow(500, 0);
+
+        assert_eq!(recursive.to_string(), iterative.to_string());
+    }
+}
+
+// a bucket from x=490 to x=510, `depth` rows deep under the spring
+#[cfg(test)]
+fn bucket(depth: u32) -> Vec<Vein> {
+    vec![
+        Vein {
+            x_start: 490,
+            x_end: 490,
+            y_start: 1,
+            y_end: depth,
+        },
+        Vein {
+            x_start: 510,
+            x_end: 510,
+            y_start: 1,
+            y_end: depth,
+        },
+        Vein {
+            x_start: 490,
+            x_end: 510,
+            y_start: depth,
+            y_end: depth,
+        },
+    ]
+}
+
+#[test]
+fn test_flow_tall() {
+    let mut recursive = Ground::from_veins(&bucket(200));
+    recursive.fill(500, 0, Flow::Down);
+    let mut iterative = Ground::from_veins(&bucket(200));
+    iterative.flow(500, 0);
+    assert_eq!(recursive.to_string(), iterative.to_string());
+
+    // deep enough to overflow the stack of a test thread with `fill`
+    let depth = 50_000;
+    let mut ground = Ground::from_veins(&bucket(depth));
+    ground.flow(500, 0);
+
+    // the bucket is full, and overflows on both sides down to the bottom
+    assert_eq!((19 * (depth - 1), 2 * depth), ground.count_water());
+}
+
+#[test]
+fn test_springs() {
+    let scan = Scan::from_str(
+        r#"spring x=500, y=0
+spring x=520, y=0
+x=495, y=2..7
+y=7, x=495..501
+x=501, y=3..7
+x=498, y=2..4
+x=506, y=1..2
+x=498, y=10..13
+x=504, y=10..13
+y=13, x=498..504"#,
+    )
+    .unwrap();
+    assert_eq!(vec![(500, 0), (520, 0)], scan.springs);
+
+    let mut ground = Ground::with_springs(&scan.veins, &scan.springs);
+    ground.flow_all();
+
+    // the second spring falls straight down, away from the clay
+    assert_eq!(vec![((500, 0), 57), ((520, 0), 13)], ground.reachable());
+    assert_eq!((29, 28 + 13), ground.count_water());
+
+    // the order of the springs does not matter
+    let springs = vec![(520, 0), (500, 0)];
+    let mut reversed = Ground::with_springs(&scan.veins, &springs);
+    reversed.flow_all();
+    assert_eq!(ground.field, reversed.field);
+}
+
+#[test]
+fn test_merging_springs() {
+    let veins = read_file("input/input17_debug.txt").unwrap().veins;
+
+    // a second spring next to the first one falls in the same reservoir, only its first tile
+    // is not already wet
+    let mut ground = Ground::with_springs(&veins, &[(500, 0), (499, 0)]);
+    ground.flow_all();
+    assert_eq!((29, 28 + 1), ground.count_water());
+
+    // a spring right above the bottom reservoir fills it without any other water, then
+    // overflows on both sides
+    let mut ground = Ground::with_springs(&veins, &[(501, 9)]);
+    ground.flow_all();
+    assert_eq!((5 * 3, 9 + 2 * 4), ground.count_water());
+}
+
+#[test]
+fn test_basins() {
+    let veins = read_file("input/input17_debug.txt").unwrap().veins;
+    let mut ground = Ground::from_veins(&veins);
+    ground.flow_all();
+
+    assert_eq!(
+        vec![
+            Basin {
+                still: 2 * 2 + 2 * 5,
+                surface: 3,
+                spills: vec![(502, 2)]
+            },
+            Basin {
+                still: 3 * 5,
+                surface: 10,
+                spills: vec![(497, 9), (505, 9)]
+            }
+        ],
+        ground.basins()
+    );
+}
+
+#[test]
+fn test_nested_basins() {
+    let scan = Scan::from_str(
+        r#"x=490, y=2..12
+x=510, y=2..12
+y=12, x=490..510
+x=497, y=6..9
+x=503, y=6..9
+y=9, x=497..503
+x=520, y=5..8
+x=526, y=5..8
+y=8, x=520..526"#,
+    )
+    .unwrap();
+    let mut ground = Ground::with_springs(&scan.veins, &scan.springs);
+    ground.flow_all();
+
+    // the inner bucket overflows into the outer one, which fills above it: they make a single
+    // basin, less the clay of the inner bucket. The bucket on the right stays dry
+    assert_eq!(
+        vec![Basin {
+            still: 10 * 19 - (2 * 4 + 5),
+            surface: 2,
+            spills: vec![(489, 1), (511, 1)]
+        }],
+        ground.basins()
+    );
+}
+
+#[test]
+fn test_spring_on_the_left_edge() {
+    assert!(Scan::from_str("spring x=0, y=0\nx=5, y=2..4").is_err());
+
+    // the water next to the first column is counted and displayed
+    let scan = Scan::from_str("spring x=1, y=0\nx=5, y=2..4").unwrap();
+    let mut ground = Ground::with_springs(&scan.veins, &scan.springs);
+    ground.flow_all();
+    assert_eq!((0, 3), ground.count_water());
+    assert!(ground
+        .to_string()
+        .starts_with("Ground:\n.+.....\n.|.....\n.|...#.\n"));
+}
+
+#[test]
+fn test_basin_with_inner_wall() {
+    let scan = Scan::from_str(
+        r#"spring x=495, y=0
+spring x=505, y=0
+x=490, y=4..12
+x=510, y=8..12
+y=12, x=490..510
+x=500, y=2..10"#,
+    )
+    .unwrap();
+    let mut ground = Ground::with_springs(&scan.veins, &scan.springs);
+    ground.flow_all();
+
+    // the wall hangs above the bottom row, joining both sides in a single basin, each side
+    // overflowing at its own level
+    assert_eq!(
+        vec![Basin {
+            still: 9 * 7 + 9 * 3 + 19,
+            surface: 4,
+            spills: vec![(489, 3), (511, 7)]
+        }],
+        ground.basins()
+    );
+}
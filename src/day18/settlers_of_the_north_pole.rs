@@ -1,183 +1,296 @@
+use crate::common::automaton::{Automaton, Boundary, Grid};
 use crate::common::error::AocError;
+use crate::common::image::Image;
 use crate::common::response::AocResponse;
 
-use itertools::Itertools;
-use std::collections::hash_map::Entry;
+use std::collections::hash_map::{DefaultHasher, Entry};
 use std::collections::HashMap;
-use std::fmt;
-use std::mem;
+use std::hash::{Hash, Hasher};
 use std::str::FromStr;
 
-#[derive(Debug, PartialEq, Eq, Copy, Clone, Hash)]
-enum Acre {
-    Open,
-    Trees,
-    Lumberyard,
-}
+// the transitions of the acres, as rules of a cellular automaton
+const RULES: &str = "input/input18_rules.txt";
 
-#[derive(Debug, PartialEq, Eq, Clone, Hash)]
 struct World {
-    grid: Vec<Vec<Acre>>,
+    automaton: Automaton,
+    grid: Grid,
 }
 
-impl fmt::Display for Acre {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match self {
-            Acre::Open => write!(f, "."),
-            Acre::Trees => write!(f, "|"),
-            Acre::Lumberyard => write!(f, "#"),
-        }
+impl World {
+    fn parse(s: &str) -> Result<World, AocError> {
+        let automaton = settlers()?;
+        let grid = automaton.parse_grid(s)?;
+        Ok(World { automaton, grid })
     }
-}
 
-impl FromStr for World {
-    type Err = AocError;
-
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let lines = s.split('\n');
-        let grid = lines
-            .filter(|line| !line.is_empty())
-            .map(|line| {
-                line.chars()
-                    .map(|ch| match ch {
-                        '.' => Acre::Open,
-                        '|' => Acre::Trees,
-                        '#' => Acre::Lumberyard,
-                        _ => panic!("invalid state"),
-                    })
-                    .collect_vec()
-            })
-            .collect_vec();
-
-        Ok(World { grid })
+    fn step(&mut self) {
+        self.grid = self.automaton.step(&self.grid);
     }
-}
 
-impl fmt::Display for World {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        for line in self.grid.iter() {
-            for c in line.iter() {
-                write!(f, "{}", c)?;
+    fn image(&self) -> Image {
+        let mut image = Image::new(self.grid.width(), self.grid.height());
+        for y in 0..self.grid.height() {
+            for x in 0..self.grid.width() {
+                let colour = match self.grid.get(x, y) {
+                    '|' => [30, 120, 40],
+                    '#' => [110, 70, 30],
+                    _ => [200, 180, 120],
+                };
+                image.set(x, y, colour);
             }
-            writeln!(f)?;
         }
+        image
+    }
 
-        Ok(())
+    #[cfg(test)]
+    fn count_resources(&self) -> (u32, u32) {
+        (self.grid.count('|') as u32, self.grid.count('#') as u32)
     }
 }
 
-impl World {
-    fn step(&mut self) {
-        let mut new = vec![vec![Acre::Open; self.width()]; self.height()];
-        for y in 0..self.height() {
-            for x in 0..self.width() {
-                self.step_cell(x, y, &mut new);
-            }
+// the acres packed 2 bits each, 32 to a word, with a border of acres marked as outside the
+// area so that every acre of the area has 8 neighbours. A step writes the next state in a second
+// buffer, the two buffers being swapped afterwards. The next state of an acre is looked up in a
+// table compiled from the rules of the automaton, for each state of the acre and number of
+// neighbours in each state.
+const OUTSIDE: u64 = 3;
+
+struct Packed {
+    width: usize,
+    height: usize,
+    // words per row, border included
+    words: usize,
+    cells: Vec<u64>,
+    next: Vec<u64>,
+    // the next state, indexed by the state of the acre and the weights of its neighbours
+    table: Vec<u8>,
+}
+
+impl Packed {
+    // the automaton must have up to 3 states, its first one being the background, the Moore
+    // neighbourhood and bounded edges
+    fn new(automaton: &Automaton, s: &str) -> Result<Packed, AocError> {
+        let cells = automaton.cells();
+        if cells.len() > 3 || !automaton.is_moore() || automaton.boundary() != Boundary::Bounded {
+            return Err(AocError::InvalidToken(
+                "only bounded automata of up to 3 states and 8 neighbours can be packed"
+                    .to_string(),
+            ));
         }
-        mem::replace(&mut self.grid, new);
-    }
 
-    fn step_cell(&self, x: usize, y: usize, new: &mut Vec<Vec<Acre>>) {
-        use self::Acre::*;
-
-        new[y][x] = self.grid[y][x];
-        match self.grid[y][x] {
-            Open => {
-                let adj_trees =
-                    self.count_neighbors(
-                        x,
-                        y,
-                        0,
-                        |count, cell| {
-                            if cell == Trees {
-                                count + 1
-                            } else {
-                                count
-                            }
-                        },
-                    );
-                if adj_trees >= 3 {
-                    new[y][x] = Trees;
-                }
-            }
-            Trees => {
-                let adj_lumber = self.count_neighbors(x, y, 0, |count, cell| {
-                    if cell == Lumberyard {
-                        count + 1
-                    } else {
-                        count
+        let mut table = vec![0; 4 << 12];
+        for acre in 0..cells.len() {
+            for first in 0..=8 {
+                for second in 0..=8 - first {
+                    for third in 0..=8 - first - second {
+                        let counts = [first, second, third];
+                        let next = automaton.next_state(acre, &counts[..cells.len()]);
+                        table[acre << 12 | third << 8 | second << 4 | first] = next as u8;
                     }
-                });
-                if adj_lumber >= 3 {
-                    new[y][x] = Lumberyard;
-                }
-            }
-            Lumberyard => {
-                let (has_lumber, has_trees) =
-                    self.count_neighbors(x, y, (false, false), |(lumber, trees), n| {
-                        (lumber || n == Lumberyard, trees || n == Trees)
-                    });
-                if has_lumber && has_trees {
-                    new[y][x] = Lumberyard;
-                } else {
-                    new[y][x] = Open;
                 }
             }
         }
-    }
 
-    // apply the fold f on the neightbours of (ox, oy)
-    fn count_neighbors<T>(
-        &self,
-        ox: usize,
-        oy: usize,
-        init: T,
-        mut f: impl FnMut(T, Acre) -> T,
-    ) -> T {
-        let mut ret = init;
-        for y in oy.saturating_sub(1)..=oy.saturating_add(1) {
-            for x in ox.saturating_sub(1)..=ox.saturating_add(1) {
-                if x == ox && y == oy {
-                    continue;
-                }
-                if x >= self.width() || y >= self.height() {
-                    continue;
-                }
-                ret = f(ret, self.grid[y][x]);
+        let rows: Vec<&str> = s.lines().filter(|l| !l.trim().is_empty()).collect();
+        let width = rows.first().map_or(0, |r| r.trim().chars().count());
+        let height = rows.len();
+        let words = (2 * (width + 2)).div_ceil(64);
+
+        let mut packed = Packed {
+            width,
+            height,
+            words,
+            cells: vec![0; words * (height + 2)],
+            next: vec![],
+            table,
+        };
+        for y in 0..height + 2 {
+            for x in 0..width + 2 {
+                packed.set(x, y, OUTSIDE);
             }
         }
-        ret
+        for (y, row) in rows.iter().enumerate() {
+            let row = row.trim();
+            if row.chars().count() != width {
+                return Err(AocError::InvalidToken(format!(
+                    "row {}: {} cells instead of {}",
+                    y + 1,
+                    row.chars().count(),
+                    width
+                )));
+            }
+            for (x, c) in row.chars().enumerate() {
+                let acre = match cells.iter().position(|&cell| cell == c) {
+                    Some(acre) => acre as u64,
+                    None => {
+                        return Err(AocError::InvalidToken(format!(
+                            "row {}: undeclared cell {:?}",
+                            y + 1,
+                            c
+                        )))
+                    }
+                };
+                packed.set(x + 1, y + 1, acre);
+            }
+        }
+        // the border never changes, so it is already there in the second buffer
+        packed.next = packed.cells.clone();
+
+        Ok(packed)
     }
 
-    fn width(&self) -> usize {
-        self.grid[0].len()
+    // position of the acre in the buffers, in acres, x and y counting the border
+    fn index(&self, x: usize, y: usize) -> usize {
+        y * self.words * 32 + x
     }
 
-    fn height(&self) -> usize {
-        self.grid.len()
+    fn get(&self, x: usize, y: usize) -> u64 {
+        let i = self.index(x, y);
+        (self.cells[i / 32] >> (2 * (i % 32))) & 3
     }
 
-    fn count_resources(&self) -> (u32, u32) {
-        let (mut wooded, mut lumber) = (0, 0);
+    fn set(&mut self, x: usize, y: usize, acre: u64) {
+        let i = self.index(x, y);
+        let shift = 2 * (i % 32);
+        self.cells[i / 32] = (self.cells[i / 32] & !(3 << shift)) | (acre << shift);
+    }
 
-        for y in 0..self.height() {
-            for x in 0..self.width() {
-                if self.grid[y][x] == Acre::Trees {
-                    wooded += 1;
-                }
-                if self.grid[y][x] == Acre::Lumberyard {
-                    lumber += 1;
-                }
+    // an acre counted in the nibble of its state
+    fn weight(acre: u64) -> u64 {
+        1 << (4 * acre)
+    }
+
+    // the weights of the column of 3 acres around (x, y)
+    fn column(&self, x: usize, y: usize) -> u64 {
+        (y - 1..=y + 1)
+            .map(|y| Packed::weight(self.get(x, y)))
+            .sum()
+    }
+
+    fn step(&mut self) {
+        for y in 1..=self.height {
+            // the counts of the 3 columns around x slide along the row
+            let (mut left, mut middle) = (self.column(0, y), self.column(1, y));
+            for x in 1..=self.width {
+                let right = self.column(x + 1, y);
+                let acre = self.get(x, y);
+                // the acres outside, in the last nibble, are not counted by the rules
+                let around = (left + middle + right - Packed::weight(acre)) & 0xfff;
+                (left, middle) = (middle, right);
+
+                let next = u64::from(self.table[(acre as usize) << 12 | around as usize]);
+                let i = self.index(x, y);
+                let shift = 2 * (i % 32);
+                self.next[i / 32] = (self.next[i / 32] & !(3 << shift)) | (next << shift);
             }
         }
+        std::mem::swap(&mut self.cells, &mut self.next);
+    }
 
-        (wooded, lumber)
+    // a hash of the state, a collision between the few hundred states seen before the area
+    // loops being very unlikely
+    fn fingerprint(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.cells.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    // number of acres in the given state
+    fn count(&self, acre: u64) -> u32 {
+        // the low and high bits of each acre, matching the ones of the state
+        let low = if acre & 1 == 1 { !0 } else { 0 };
+        let high = if acre & 2 == 2 { !0 } else { 0 };
+        self.cells
+            .iter()
+            .map(|&word| {
+                let matches = !(word ^ low) & !((word >> 1) ^ high);
+                (matches & 0x5555_5555_5555_5555).count_ones()
+            })
+            .sum::<u32>()
+            - if acre == 0 {
+                // the unused bits at the end of each row read as the first state
+                (self.height as u32 + 2) * (self.words * 32 - self.width - 2) as u32
+            } else {
+                0
+            }
     }
+
+    fn count_resources(&self, cells: &[char]) -> (u32, u32) {
+        let count = |c| match cells.iter().position(|&cell| cell == c) {
+            Some(acre) => self.count(acre as u64),
+            None => 0,
+        };
+        (count('|'), count('#'))
+    }
+}
+
+fn settlers() -> Result<Automaton, AocError> {
+    let rules = std::fs::read_to_string(RULES)?;
+    Automaton::from_str(&rules)
+}
+
+// save the area after the given number of minutes as a .png or .ppm image
+pub fn write_image(filename: &str, output: &str, minutes: usize) -> Result<(), AocError> {
+    let s = std::fs::read_to_string(filename)?;
+    let mut world = World::parse(&s)?;
+
+    (0..minutes).for_each(|_| world.step());
+    world.image().scale(8)?.save(output)
 }
 
 pub fn answer1() -> Result<AocResponse<u32>, AocError> {
     let s = std::fs::read_to_string("input/input18.txt")?;
-    let mut world = World::from_str(&s)?;
+    let automaton = settlers()?;
+    let mut area = Packed::new(&automaton, &s)?;
+
+    (0..10).for_each(|_| area.step());
+    let resources = area.count_resources(automaton.cells());
+
+    Ok(AocResponse::new(
+        18,
+        1,
+        "Settlers of The North Pole",
+        resources.0 * resources.1,
+    ))
+}
+
+pub fn answer2() -> Result<AocResponse<u32>, AocError> {
+    let s = std::fs::read_to_string("input/input18.txt")?;
+    let automaton = settlers()?;
+    let mut area = Packed::new(&automaton, &s)?;
+
+    let mut seen = HashMap::new();
+
+    let mut i = 0u64;
+    let period = loop {
+        match seen.entry(area.fingerprint()) {
+            Entry::Occupied(o) => break i - *o.get(),
+            Entry::Vacant(v) => {
+                v.insert(i);
+            }
+        }
+
+        area.step();
+        i += 1;
+    };
+
+    let remaining = (1_000_000_000 - i) % period;
+    (0..remaining).for_each(|_| area.step());
+    let resources = area.count_resources(automaton.cells());
+
+    Ok(AocResponse::new(
+        18,
+        2,
+        "Settlers of The North Pole",
+        resources.0 * resources.1,
+    ))
+}
+
+// the same answers computed by the generic automaton, to check the packed area against it
+#[cfg(test)]
+fn answer1_rules() -> Result<AocResponse<u32>, AocError> {
+    let s = std::fs::read_to_string("input/input18.txt")?;
+    let mut world = World::parse(&s)?;
 
     (0..10).for_each(|_| world.step());
     let resources = world.count_resources();
@@ -190,15 +303,16 @@ pub fn answer1() -> Result<AocResponse<u32>, AocError> {
     ))
 }
 
-pub fn answer2() -> Result<AocResponse<u32>, AocError> {
+#[cfg(test)]
+fn answer2_rules() -> Result<AocResponse<u32>, AocError> {
     let s = std::fs::read_to_string("input/input18.txt")?;
-    let mut world = World::from_str(&s)?;
+    let mut world = World::parse(&s)?;
 
     let mut seen = HashMap::new();
 
-    let mut i = 0;
+    let mut i = 0u64;
     let (mut current_i, period) = loop {
-        match seen.entry(world.clone()) {
+        match seen.entry(world.grid.clone()) {
             Entry::Occupied(o) => {
                 let prev_i = *o.get();
                 let period = i - prev_i;
@@ -214,17 +328,15 @@ pub fn answer2() -> Result<AocResponse<u32>, AocError> {
     };
 
     let target_i = 1_000_000_000u64;
-    let target_modulo = target_i % period as u64;
+    let target_modulo = target_i % period;
 
-    let mut current_mod = current_i % period as u64;
+    let mut current_mod = current_i % period;
     while current_mod != target_modulo {
         world.step();
         current_i += 1;
         current_mod = current_i % period;
     }
 
-    //println!("period: {}, target_modulo: {}, final_i: {}", period, target_modulo, current_i);
-
     let resources = world.count_resources();
 
     Ok(AocResponse::new(
@@ -237,7 +349,7 @@ pub fn answer2() -> Result<AocResponse<u32>, AocError> {
 
 #[test]
 fn test_parse() {
-    let world = World::from_str(
+    let world = World::parse(
         r#"
 .#.#...|#.
 .....#|##|
@@ -251,15 +363,15 @@ fn test_parse() {
 ...#.|..|."#,
     )
     .unwrap();
-    println!("{}", world);
+    println!("{}", world.grid);
 
-    assert_eq!(world.grid.len(), 10);
-    assert_eq!(world.grid[0].len(), 10);
+    assert_eq!(world.grid.height(), 10);
+    assert_eq!(world.grid.width(), 10);
 }
 
 #[test]
 fn test_step() {
-    let mut world = World::from_str(
+    let mut world = World::parse(
         r#"
 .#.#...|#.
 .....#|##|
@@ -274,9 +386,9 @@ fn test_step() {
     )
     .unwrap();
     world.step();
-    println!("test: {}", world);
+    println!("test: {}", world.grid);
 
-    let expected = World::from_str(
+    let expected = World::parse(
         r#"
 .......##.
 ......|###
@@ -291,12 +403,12 @@ fn test_step() {
     )
     .unwrap();
 
-    assert_eq!(expected, world);
+    assert_eq!(expected.grid, world.grid);
 }
 
 #[test]
 fn test_count_resources() {
-    let mut world = World::from_str(
+    let mut world = World::parse(
         r#"
 .#.#...|#.
 .....#|##|
@@ -314,3 +426,83 @@ fn test_count_resources() {
 
     assert_eq!((37, 31), world.count_resources());
 }
+
+#[test]
+fn test_invalid_acre() {
+    match World::parse("..#\n.x|\n") {
+        Err(AocError::InvalidToken(msg)) => assert_eq!("row 2: undeclared cell 'x'", msg),
+        _ => panic!("the grid should not parse"),
+    }
+}
+
+#[test]
+fn test_packed() {
+    let automaton = settlers().unwrap();
+    let s = std::fs::read_to_string("input/input18_debug.txt").unwrap();
+    let mut world = World::parse(&s).unwrap();
+    let mut area = Packed::new(&automaton, &s).unwrap();
+    // the area is wider than a word, so rows span several of them
+    let wide = format!("{}\n", ".|#".repeat(15)).repeat(3);
+    let mut wide_world = World::parse(&wide).unwrap();
+    let mut wide_area = Packed::new(&automaton, &wide).unwrap();
+    assert_eq!(2, wide_area.words);
+
+    let cells = automaton.cells();
+    for _ in 0..10 {
+        assert_eq!(world.count_resources(), area.count_resources(cells));
+        assert_eq!(
+            wide_world.count_resources(),
+            wide_area.count_resources(cells)
+        );
+        for y in 0..wide_world.grid.height() {
+            for x in 0..wide_world.grid.width() {
+                let acre = cells.iter().position(|&c| c == wide_world.grid.get(x, y));
+                assert_eq!(acre, Some(wide_area.get(x + 1, y + 1) as usize));
+            }
+        }
+        world.step();
+        area.step();
+        wide_world.step();
+        wide_area.step();
+    }
+    assert_eq!((37, 31), area.count_resources(cells));
+    assert_eq!(100 - 37 - 31, area.count(0));
+
+    match Packed::new(&automaton, "..#\n.x|\n") {
+        Err(AocError::InvalidToken(msg)) => assert_eq!("row 2: undeclared cell 'x'", msg),
+        _ => panic!("the area should not parse"),
+    }
+}
+
+#[test]
+fn test_answers_rules() {
+    assert_eq!(answer1().unwrap().get(), answer1_rules().unwrap().get());
+    assert_eq!(answer2().unwrap().get(), answer2_rules().unwrap().get());
+}
+
+#[test]
+fn test_packed_rules() {
+    // any bounded automaton of up to 3 states can be packed, such as the game of life
+    let life = Automaton::from_str(
+        "cells: . #\n# if count(#) < 2 or count(#) > 3 -> .\n. if count(#) == 3 -> #",
+    )
+    .unwrap();
+    let start = ".#......\n..#.....\n###.....\n........\n........\n";
+    let mut grid = life.parse_grid(start).unwrap();
+    let mut packed = Packed::new(&life, start).unwrap();
+    for _ in 0..30 {
+        grid = life.step(&grid);
+        packed.step();
+        for y in 0..grid.height() {
+            for x in 0..grid.width() {
+                let cell = if grid.get(x, y) == '#' { 1 } else { 0 };
+                assert_eq!(cell, packed.get(x + 1, y + 1));
+            }
+        }
+    }
+    // the glider ends up as a block in the bottom right corner
+    assert_eq!(4, packed.count(1));
+
+    let torus = Automaton::from_str("cells: . #\nboundary: toroidal").unwrap();
+    assert!(Packed::new(&torus, start).is_err());
+}
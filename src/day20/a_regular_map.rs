@@ -1,10 +1,8 @@
 use crate::common::error::AocError;
 use crate::common::response::AocResponse;
 
-use regex_syntax::hir::{self, Hir, HirKind};
-use regex_syntax::ParserBuilder;
-use std::cmp;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
 
 #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
 struct Coord {
@@ -39,52 +37,181 @@ impl Coord {
 // discovering some kind of type alias in rust
 type Distances = HashMap<Coord, usize>;
 
-// TODO: trying a proper Result response, so it seems I must return something, so I'm returning something meaningless
-fn distances(expr: &Hir, dists: &mut Distances, c: Coord) -> Result<Coord, AocError> {
-    match *expr.kind() {
-        HirKind::Literal(hir::Literal::Unicode(ch)) => {
-            let nextc = c.mv(ch)?;
-            let mut dist = dists[&c] + 1;
-            if dists.contains_key(&nextc) {
-                dist = cmp::min(dist, dists[&nextc])
+// the doors of a room
+const NORTH: u8 = 1;
+const SOUTH: u8 = 2;
+const WEST: u8 = 4;
+const EAST: u8 = 8;
+
+// the rooms reached by the route and the doors between them
+#[derive(Debug, Default)]
+struct Map {
+    rooms: HashMap<Coord, u8>,
+}
+
+impl Map {
+    // go through the door of `c` in the given direction, returning the room behind it
+    fn open(&mut self, c: Coord, direction: char) -> Result<Coord, AocError> {
+        let next = c.mv(direction)?;
+        let (door, back) = match direction {
+            'N' => (NORTH, SOUTH),
+            'S' => (SOUTH, NORTH),
+            'W' => (WEST, EAST),
+            _ => (EAST, WEST),
+        };
+        *self.rooms.entry(c).or_insert(0) |= door;
+        *self.rooms.entry(next).or_insert(0) |= back;
+        Ok(next)
+    }
+
+    fn doors(&self, c: Coord) -> u8 {
+        self.rooms.get(&c).cloned().unwrap_or(0)
+    }
+
+    // the least number of doors to go through to reach each room from the origin
+    fn distances(&self) -> Distances {
+        let origin = Coord { x: 0, y: 0 };
+        let mut dists = Distances::new();
+        dists.insert(origin, 0);
+
+        let mut queue = VecDeque::new();
+        queue.push_back(origin);
+        while let Some(c) = queue.pop_front() {
+            let doors = self.doors(c);
+            for &(door, direction) in &[(NORTH, 'N'), (SOUTH, 'S'), (WEST, 'W'), (EAST, 'E')] {
+                if doors & door == 0 {
+                    continue;
+                }
+                let next = c.mv(direction).unwrap();
+                if !dists.contains_key(&next) {
+                    dists.insert(next, dists[&c] + 1);
+                    queue.push_back(next);
+                }
             }
-            dists.insert(nextc, dist);
-            Ok(nextc)
         }
-        HirKind::Group(ref g) => distances(&g.hir, dists, c),
-        HirKind::Concat(ref exprs) => {
-            let mut nextc = c;
-            for e in exprs {
-                nextc = distances(e, dists, nextc)?;
+
+        dists
+    }
+
+    // the map as the puzzle draws it, walls being #, doors | or - and the origin X
+    fn render(&self) -> String {
+        let origin = Coord { x: 0, y: 0 };
+        let rooms = || self.rooms.keys().chain(std::iter::once(&origin));
+        let min_x = rooms().map(|c| c.x).min().unwrap();
+        let max_x = rooms().map(|c| c.x).max().unwrap();
+        let min_y = rooms().map(|c| c.y).min().unwrap();
+        let max_y = rooms().map(|c| c.y).max().unwrap();
+
+        let width = 2 * (max_x - min_x + 1) as usize + 1;
+        let height = 2 * (max_y - min_y + 1) as usize + 1;
+        let mut grid = vec![vec!['#'; width]; height];
+        for (&c, &doors) in &self.rooms {
+            let x = 2 * (c.x - min_x) as usize + 1;
+            let y = 2 * (c.y - min_y) as usize + 1;
+            grid[y][x] = '.';
+            if doors & EAST != 0 {
+                grid[y][x + 1] = '|';
             }
-            Ok(nextc)
-        }
-        HirKind::Alternation(ref exprs) => {
-            for e in exprs {
-                distances(e, dists, c)?;
+            if doors & SOUTH != 0 {
+                grid[y + 1][x] = '-';
             }
-            Ok(c)
         }
-        _ => Ok(c), // we don't care
+        let (x, y) = (2 * -min_x as usize + 1, 2 * -min_y as usize + 1);
+        grid[y][x] = 'X';
+
+        grid.into_iter()
+            .map(|row| row.into_iter().collect::<String>() + "\n")
+            .collect()
     }
 }
 
-fn build_distances(s: &str) -> Result<Distances, AocError> {
-    // use regex-syntax to build a high-level intermediate representation ("HIR") of regular expression
-    // https://docs.rs/regex-syntax/0.6.4/regex_syntax/
-    let expr = ParserBuilder::new()
-        .nest_limit(1000)
-        .build()
-        .parse(s.trim())
-        .unwrap();
-
-    let mut dists = Distances::new();
+impl fmt::Display for Map {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.render())
+    }
+}
+
+// a group of branches being read: the rooms where each branch starts, the rooms where the
+// branches read so far end, and the position of its '('
+struct Group {
+    starts: HashSet<Coord>,
+    ends: HashSet<Coord>,
+    position: usize,
+}
+
+// read the route `^...$` one character at a time, opening the doors from every room the route
+// may have reached. The groups being kept on a stack, they can be nested arbitrarily deep, and
+// a branch can be empty as in `(NEWS|)`.
+fn build_map(s: &str) -> Result<Map, AocError> {
+    let error = |position: usize, msg: String| {
+        AocError::InvalidToken(format!("position {}: {}", position + 1, msg))
+    };
+    let route = s.trim();
+    let mut chars = route.char_indices();
+    match chars.next() {
+        Some((_, '^')) => (),
+        _ => return Err(error(0, "expected '^'".to_string())),
+    }
+
+    let mut map = Map::default();
     let origin = Coord { x: 0, y: 0 };
-    dists.insert(origin, 0);
+    let mut current: HashSet<Coord> = [origin].iter().cloned().collect();
+    let mut groups: Vec<Group> = vec![];
+
+    loop {
+        let (position, c) = match chars.next() {
+            Some(next) => next,
+            None => return Err(error(route.len(), "expected '$'".to_string())),
+        };
+        match c {
+            'N' | 'S' | 'W' | 'E' => {
+                current = current
+                    .into_iter()
+                    .map(|room| map.open(room, c))
+                    .collect::<Result<_, _>>()?;
+            }
+            '(' => groups.push(Group {
+                starts: current.clone(),
+                ends: HashSet::new(),
+                position,
+            }),
+            '|' => {
+                let group = groups
+                    .last_mut()
+                    .ok_or_else(|| error(position, "'|' outside of a group".to_string()))?;
+                group.ends.extend(current);
+                current = group.starts.clone();
+            }
+            ')' => {
+                let mut group = groups
+                    .pop()
+                    .ok_or_else(|| error(position, "unmatched ')'".to_string()))?;
+                group.ends.extend(current);
+                current = group.ends;
+            }
+            '$' => {
+                if let Some(group) = groups.last() {
+                    return Err(error(group.position, "unclosed '('".to_string()));
+                }
+                if let Some((position, c)) = chars.next() {
+                    return Err(error(position, format!("unexpected {:?} after '$'", c)));
+                }
+                return Ok(map);
+            }
+            _ => return Err(error(position, format!("unexpected {:?}", c))),
+        }
+    }
+}
 
-    distances(&expr, &mut dists, origin)?;
+fn build_distances(s: &str) -> Result<Distances, AocError> {
+    Ok(build_map(s)?.distances())
+}
 
-    Ok(dists)
+// print the map of the rooms and doors reached by the route
+pub fn print_map(filename: &str) -> Result<(), AocError> {
+    let s = std::fs::read_to_string(filename)?;
+    print!("{}", build_map(&s)?);
+    Ok(())
 }
 
 fn max_distance(dists: &Distances) -> usize {
@@ -133,3 +260,86 @@ fn test_distance_3() {
 
     assert_eq!(18, result);
 }
+
+#[test]
+fn test_render() {
+    assert_eq!(
+        r#"#####
+#.|.#
+#-###
+#.|X#
+#####
+"#,
+        build_map("^WNE$").unwrap().to_string()
+    );
+
+    assert_eq!(
+        r#"#########
+#.|.|.|.#
+#-#######
+#.|.|.|.#
+#-#####-#
+#.#.#X|.#
+#-#-#####
+#.|.|.|.#
+#########
+"#,
+        build_map("^ENWWW(NEEE|SSE(EE|N))$").unwrap().to_string()
+    );
+
+    assert_eq!(
+        r#"###########
+#.|.#.|.#.#
+#-###-#-#-#
+#.|.|.#.#.#
+#-#####-#-#
+#.#.#X|.#.#
+#-#-#####-#
+#.#.|.|.|.#
+#-###-###-#
+#.|.|.#.|.#
+###########
+"#,
+        build_map("^ENNWSWW(NEWS|)SSSEEN(WNSE|)EE(SWEN|)NNN$")
+            .unwrap()
+            .to_string()
+    );
+}
+
+#[test]
+fn test_branch_ends() {
+    // the route goes on from the end of each branch
+    let map = build_map("^(N|E)E$").unwrap();
+    assert_eq!(5, map.rooms.len());
+    assert_eq!(WEST, map.doors(Coord { x: 1, y: -1 }) & WEST);
+    assert_eq!(
+        2,
+        build_distances("^(N|E)E$").unwrap()[&Coord { x: 2, y: 0 }]
+    );
+}
+
+#[test]
+fn test_deep_nesting() {
+    let depth = 100_000;
+    let route = format!("^{}{}$", "(E|".repeat(depth), ")".repeat(depth));
+    let dists = build_distances(&route).unwrap();
+    assert_eq!(2, dists.len());
+
+    let route = format!("^{}{}$", "(E".repeat(depth), ")".repeat(depth));
+    assert_eq!(depth, max_distance(&build_distances(&route).unwrap()));
+}
+
+#[test]
+fn test_syntax_errors() {
+    let err = |s: &str| match build_map(s) {
+        Err(AocError::InvalidToken(msg)) => msg,
+        other => panic!("unexpected {:?}", other),
+    };
+    assert_eq!("position 1: expected '^'", err("WNE$"));
+    assert_eq!("position 5: expected '$'", err("^WNE"));
+    assert_eq!("position 3: unexpected 'X'", err("^WXE$"));
+    assert_eq!("position 4: '|' outside of a group", err("^WN|E$"));
+    assert_eq!("position 5: unmatched ')'", err("^(N))$"));
+    assert_eq!("position 3: unclosed '('", err("^N(E(W|S)$"));
+    assert_eq!("position 6: unexpected 'N' after '$'", err("^(N)$N"));
+}
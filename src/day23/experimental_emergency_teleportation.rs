@@ -3,16 +3,18 @@ use crate::common::response::AocResponse;
 
 use lazy_static::lazy_static;
 use regex::Regex;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
 use std::fs::File;
 use std::io::{BufRead, BufReader};
 use std::str::FromStr;
 
 #[derive(Debug, PartialEq, Eq, Clone)]
-struct Nanobot {
-    x: i64,
-    y: i64,
-    z: i64,
-    r: u64,
+pub struct Nanobot {
+    pub x: i64,
+    pub y: i64,
+    pub z: i64,
+    pub r: u64,
 }
 
 impl FromStr for Nanobot {
@@ -37,12 +39,21 @@ impl FromStr for Nanobot {
 }
 
 impl Nanobot {
-    fn distance_to(&self, other: &Nanobot) -> u64 {
+    pub fn distance_to(&self, other: &Nanobot) -> u64 {
         ((self.x - other.x).abs() + (self.y - other.y).abs() + (self.z - other.z).abs()) as u64
     }
 
+    pub fn covers(&self, x: i64, y: i64, z: i64) -> bool {
+        ((self.x - x).abs() + (self.y - y).abs() + (self.z - z).abs()) as u64 <= self.r
+    }
+
+    // whether at least one point is in range of both nanobots
+    pub fn overlaps(&self, other: &Nanobot) -> bool {
+        self.distance_to(other) <= self.r + other.r
+    }
+
     // a copy of all the nanobots in range from the given list
-    fn in_range(&self, others: &[Nanobot]) -> Vec<Nanobot> {
+    pub fn in_range(&self, others: &[Nanobot]) -> Vec<Nanobot> {
         others
             .iter()
             .filter(|n| self.distance_to(&n) <= self.r)
@@ -51,6 +62,304 @@ impl Nanobot {
     }
 }
 
+// an axis aligned cube of side `size`, with (x, y, z) its corner with the smallest coordinates
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
+pub struct Cube {
+    pub x: i64,
+    pub y: i64,
+    pub z: i64,
+    pub size: i64,
+}
+
+impl Cube {
+    // the smallest cube with a power of two side containing the origin and the whole range of
+    // all the nanobots, the best point being possibly far from any nanobot
+    fn around(nanobots: &[Nanobot]) -> Cube {
+        let min = |f: fn(&Nanobot) -> i64| nanobots.iter().map(f).min().unwrap_or(0).min(0);
+        let max = |f: fn(&Nanobot) -> i64| nanobots.iter().map(f).max().unwrap_or(0).max(0);
+        let (x, y, z) = (
+            min(|n| n.x - n.r as i64),
+            min(|n| n.y - n.r as i64),
+            min(|n| n.z - n.r as i64),
+        );
+        let span = (max(|n| n.x + n.r as i64) - x)
+            .max(max(|n| n.y + n.r as i64) - y)
+            .max(max(|n| n.z + n.r as i64) - z)
+            + 1;
+
+        let mut size = 1;
+        while size < span {
+            size *= 2;
+        }
+        Cube { x, y, z, size }
+    }
+
+    // manhattan distance from a point to the closest point of the cube
+    fn distance_to(&self, x: i64, y: i64, z: i64) -> u64 {
+        let axis = |low: i64, v: i64| {
+            let high = low + self.size - 1;
+            if v < low {
+                low - v
+            } else if v > high {
+                v - high
+            } else {
+                0
+            }
+        };
+        (axis(self.x, x) + axis(self.y, y) + axis(self.z, z)) as u64
+    }
+
+    // number of nanobots with at least one point of the cube in range
+    fn in_range(&self, nanobots: &[Nanobot]) -> usize {
+        nanobots
+            .iter()
+            .filter(|n| self.distance_to(n.x, n.y, n.z) <= n.r)
+            .count()
+    }
+
+    fn split(&self) -> Vec<Cube> {
+        let size = self.size / 2;
+        let mut cubes = Vec::with_capacity(8);
+        for &dx in &[0, size] {
+            for &dy in &[0, size] {
+                for &dz in &[0, size] {
+                    cubes.push(Cube {
+                        x: self.x + dx,
+                        y: self.y + dy,
+                        z: self.z + dz,
+                        size,
+                    });
+                }
+            }
+        }
+        cubes
+    }
+}
+
+// a fixed size set of small integers, for the clique search on ~1000 nanobots
+#[derive(Clone)]
+struct BitSet {
+    words: Vec<u64>,
+}
+
+impl BitSet {
+    fn new(size: usize) -> Self {
+        BitSet {
+            words: vec![0; size.div_ceil(64)],
+        }
+    }
+
+    fn insert(&mut self, i: usize) {
+        self.words[i / 64] |= 1 << (i % 64);
+    }
+
+    fn remove(&mut self, i: usize) {
+        self.words[i / 64] &= !(1 << (i % 64));
+    }
+
+    fn len(&self) -> usize {
+        self.words.iter().map(|w| w.count_ones() as usize).sum()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.words.iter().all(|&w| w == 0)
+    }
+
+    fn intersection(&self, other: &BitSet) -> BitSet {
+        let words = self.words.iter().zip(&other.words).map(|(a, b)| a & b);
+        BitSet {
+            words: words.collect(),
+        }
+    }
+
+    fn difference(&self, other: &BitSet) -> BitSet {
+        let words = self.words.iter().zip(&other.words).map(|(a, b)| a & !b);
+        BitSet {
+            words: words.collect(),
+        }
+    }
+
+    fn iter<'a>(&'a self) -> impl Iterator<Item = usize> + 'a {
+        self.words.iter().enumerate().flat_map(|(i, &w)| {
+            (0..64)
+                .filter(move |b| w & (1 << b) != 0)
+                .map(move |b| i * 64 + b)
+        })
+    }
+}
+
+pub struct Swarm {
+    nanobots: Vec<Nanobot>,
+}
+
+impl FromStr for Swarm {
+    type Err = AocError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let nanobots = s
+            .lines()
+            .filter(|l| !l.trim().is_empty())
+            .map(Nanobot::from_str)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Swarm::new(nanobots))
+    }
+}
+
+impl Swarm {
+    pub fn new(nanobots: Vec<Nanobot>) -> Self {
+        Swarm { nanobots }
+    }
+
+    pub fn nanobots(&self) -> &[Nanobot] {
+        &self.nanobots
+    }
+
+    // number of nanobots having the point in range
+    pub fn coverage(&self, x: i64, y: i64, z: i64) -> usize {
+        self.nanobots.iter().filter(|n| n.covers(x, y, z)).count()
+    }
+
+    // indexes of the other nanobots whose range intersects the range of the i-th one
+    pub fn overlapping(&self, i: usize) -> Vec<usize> {
+        let bot = &self.nanobots[i];
+        (0..self.nanobots.len())
+            .filter(|&j| j != i && bot.overlaps(&self.nanobots[j]))
+            .collect()
+    }
+
+    pub fn overlapping_pairs(&self) -> Vec<(usize, usize)> {
+        let mut pairs = vec![];
+        for (i, a) in self.nanobots.iter().enumerate() {
+            for (j, b) in self.nanobots.iter().enumerate().skip(i + 1) {
+                if a.overlaps(b) {
+                    pairs.push((i, j));
+                }
+            }
+        }
+        pairs
+    }
+
+    // the largest set of nanobots whose ranges pairwise intersect (Bron-Kerbosch with pivoting)
+    // pairwise intersection does not guarantee that a point is in range of all of them
+    pub fn max_clique(&self) -> Vec<usize> {
+        let n = self.nanobots.len();
+        let neighbours: Vec<BitSet> = (0..n)
+            .map(|i| {
+                let mut set = BitSet::new(n);
+                for j in self.overlapping(i) {
+                    set.insert(j);
+                }
+                set
+            })
+            .collect();
+
+        let mut candidates = BitSet::new(n);
+        for i in 0..n {
+            candidates.insert(i);
+        }
+
+        let mut best = vec![];
+        Self::bron_kerbosch(
+            &neighbours,
+            &mut vec![],
+            candidates,
+            BitSet::new(n),
+            &mut best,
+        );
+        best.sort();
+        best
+    }
+
+    fn bron_kerbosch(
+        neighbours: &[BitSet],
+        clique: &mut Vec<usize>,
+        mut candidates: BitSet,
+        mut excluded: BitSet,
+        best: &mut Vec<usize>,
+    ) {
+        if candidates.is_empty() {
+            if excluded.is_empty() && clique.len() > best.len() {
+                *best = clique.clone();
+            }
+            return;
+        }
+        // no way to beat the best clique found so far
+        if clique.len() + candidates.len() <= best.len() {
+            return;
+        }
+
+        let pivot = candidates
+            .iter()
+            .chain(excluded.iter())
+            .max_by_key(|&p| candidates.intersection(&neighbours[p]).len())
+            .unwrap();
+        let to_visit: Vec<usize> = candidates.difference(&neighbours[pivot]).iter().collect();
+
+        for v in to_visit {
+            clique.push(v);
+            Self::bron_kerbosch(
+                neighbours,
+                clique,
+                candidates.intersection(&neighbours[v]),
+                excluded.intersection(&neighbours[v]),
+                best,
+            );
+            clique.pop();
+
+            candidates.remove(v);
+            excluded.insert(v);
+        }
+    }
+
+    // the (at most) k disjoint cubes of side `size` (rounded up to a power of two) touching the
+    // range of the most nanobots, the closest to the origin first in case of tie
+    // branch and bound: explore first the cubes in range of the most nanobots, the count of a cube
+    // only decreasing when splitting it a cube of the final size cannot be beaten by any other left
+    pub fn top_regions(&self, k: usize, size: i64) -> Vec<(Cube, usize)> {
+        let mut regions = vec![];
+        if self.nanobots.is_empty() {
+            return regions;
+        }
+
+        let mut heap = BinaryHeap::new();
+        let cube = Cube::around(&self.nanobots);
+        heap.push((
+            cube.in_range(&self.nanobots),
+            Reverse(cube.distance_to(0, 0, 0)),
+            Reverse(cube.size),
+            cube,
+        ));
+
+        while let Some((count, _, _, cube)) = heap.pop() {
+            if regions.len() >= k {
+                break;
+            }
+            if cube.size <= size.max(1) {
+                regions.push((cube, count));
+                continue;
+            }
+            for sub in cube.split() {
+                heap.push((
+                    sub.in_range(&self.nanobots),
+                    Reverse(sub.distance_to(0, 0, 0)),
+                    Reverse(sub.size),
+                    sub,
+                ));
+            }
+        }
+
+        regions
+    }
+
+    // the point in range of the most nanobots, the closest to the origin in case of tie
+    pub fn best_position(&self) -> Option<(i64, i64, i64)> {
+        self.top_regions(1, 1)
+            .first()
+            .map(|(cube, _)| (cube.x, cube.y, cube.z))
+    }
+}
+
 fn read_file(filename: &str) -> Result<Vec<Nanobot>, AocError> {
     let file = File::open(filename)?;
     let reader = BufReader::new(file);
@@ -79,6 +388,27 @@ pub fn answer1() -> Result<AocResponse<usize>, AocError> {
     ))
 }
 
+pub fn answer2() -> Result<AocResponse<i64>, AocError> {
+    let swarm = Swarm::new(read_file("input/input23.txt")?);
+
+    let (x, y, z) = swarm.best_position().ok_or(AocError::ComputeNotFound)?;
+
+    Ok(AocResponse::new(
+        23,
+        2,
+        "Experimental Emergency Teleportation",
+        x.abs() + y.abs() + z.abs(),
+    ))
+}
+
+#[cfg(test)]
+const EXAMPLE: &str = r#"pos=<10,12,12>, r=2
+pos=<12,14,12>, r=2
+pos=<16,12,12>, r=4
+pos=<14,14,14>, r=6
+pos=<50,50,50>, r=200
+pos=<10,10,10>, r=5"#;
+
 #[test]
 fn test_nanobot_distance() {
     let origin = Nanobot {
@@ -125,3 +455,63 @@ fn test_in_range() {
     assert_eq!(4, max_radius_nanobot.r);
     assert_eq!(7, in_range.len());
 }
+
+#[test]
+fn test_best_position() {
+    let swarm = Swarm::from_str(EXAMPLE).unwrap();
+
+    assert_eq!(Some((12, 12, 12)), swarm.best_position());
+}
+
+#[test]
+fn test_best_position_outside_the_nanobots() {
+    // the origin is in range, far from the only nanobot
+    let swarm = Swarm::from_str("pos=<10,0,0>, r=100").unwrap();
+    assert_eq!(Some((0, 0, 0)), swarm.best_position());
+
+    let swarm = Swarm::from_str("pos=<-50,20,30>, r=100").unwrap();
+    assert_eq!(Some((0, 0, 0)), swarm.best_position());
+}
+
+#[test]
+fn test_swarm_queries() {
+    let swarm = Swarm::from_str(EXAMPLE).unwrap();
+
+    assert_eq!(5, swarm.coverage(12, 12, 12));
+    assert_eq!(1, swarm.coverage(100, 100, 100));
+
+    assert_eq!(vec![1, 2, 3, 4, 5], swarm.overlapping(0));
+    assert!(!swarm.overlapping_pairs().contains(&(2, 5)));
+    assert_eq!(vec![0, 1, 2, 3, 4], swarm.max_clique());
+
+    let regions = swarm.top_regions(3, 1);
+    assert_eq!(3, regions.len());
+    assert_eq!(
+        (
+            Cube {
+                x: 12,
+                y: 12,
+                z: 12,
+                size: 1
+            },
+            5
+        ),
+        regions[0]
+    );
+    assert!(regions
+        .iter()
+        .all(|(cube, count)| { *count == swarm.coverage(cube.x, cube.y, cube.z) }));
+}
+
+#[test]
+fn test_top_regions_outside_the_nanobots() {
+    // the centres are a single point, the regions in range of both nanobots lie around it
+    let swarm = Swarm::from_str("pos=<100,0,0>, r=10\npos=<100,0,0>, r=20").unwrap();
+
+    let corners: Vec<_> = swarm
+        .top_regions(2, 1)
+        .into_iter()
+        .map(|(cube, count)| (cube.x, cube.y, cube.z, count))
+        .collect();
+    assert_eq!(vec![(90, 0, 0, 2), (91, 0, 0, 2)], corners);
+}
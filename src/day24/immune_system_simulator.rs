@@ -1,93 +1,87 @@
 use crate::common::error::AocError;
 use crate::common::response::AocResponse;
 
-#[derive(Debug, PartialEq, Eq, Hash, Clone)]
-enum Element {
-    FIRE,
-    COLD,
-    SLASHING,
-    BLUDGEONING,
-    RADIATION,
-}
-
-impl Element {
-    fn parse(s: &str) -> Element {
-        match s {
-            "radiation" => Element::RADIATION,
-            "bludgeoning" => Element::BLUDGEONING,
-            "fire" => Element::FIRE,
-            "cold" => Element::COLD,
-            "slashing" => Element::SLASHING,
-            s => panic!("{:?} is not an element", s),
-        }
-    }
-}
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use std::cmp;
+use std::ops::Range;
+use std::str::FromStr;
+use std::thread;
+use std::time::Duration;
+
+const IMMUNE_SYSTEM: &str = "Immune System";
 
 #[derive(Debug, PartialEq, Eq, Hash, Clone)]
 struct Army {
-    infection: bool,
+    // index of the team in the order of the input
+    team: usize,
+    // number of the group in its team, starting at 1
+    group: usize,
     size: u64,
     hit_points: u64,
-    immune: Vec<Element>,
-    weak: Vec<Element>,
+    immune: Vec<String>,
+    weak: Vec<String>,
     attack: u64,
-    attack_type: Element,
+    attack_type: String,
     initiative: u64,
 }
 
 impl Army {
-    fn parse(s: &str, infection: bool) -> Army {
-        let tokens: Vec<&str> = s.split(' ').collect();
-        let size = tokens[0].parse::<u64>().unwrap();
-        let hit_points = tokens[4].parse::<u64>().unwrap();
-        let initiative = tokens[tokens.len() - 1].parse::<u64>().unwrap();
-        let attack = tokens[tokens.len() - 6].parse::<u64>().unwrap();
-        let attack_type = Element::parse(tokens[tokens.len() - 5]);
+    // parse a group description, `line` being its 1-based line number in the input
+    fn parse(s: &str, line: usize, team: usize, group: usize) -> Result<Army, AocError> {
+        lazy_static! {
+            static ref RE: Regex = Regex::new(
+                r"^(\d+) units? each with (\d+) hit points? (?:\(([^)]*)\) )?with an attack that does (\d+) (\w+) damage at initiative (\d+)$"
+            )
+            .unwrap();
+        }
+
+        let caps = match RE.captures(s.trim()) {
+            Some(caps) => caps,
+            None => return Err(format!("line {}: invalid group {:?}", line, s))?,
+        };
+        let number = |i: usize| {
+            caps[i].parse::<u64>().map_err(|_| {
+                AocError::InvalidToken(format!("line {}: {} is too big", line, &caps[i]))
+            })
+        };
 
         let mut immune = vec![];
         let mut weak = vec![];
-        if s.contains('(') {
-            let spec: String = s
-                .chars()
-                .skip_while(|&c| c != '(')
-                .take_while(|&c| c != ')')
-                .collect();
-            for sub in spec.split("; ") {
-                let elements: Vec<_> = sub
-                    .split(' ')
-                    .skip(2)
-                    .map(|e| Element::parse(e.trim_end_matches(|x| ";),".contains(x))))
-                    .collect();
-                if sub.trim_start_matches('(').starts_with("weak") {
-                    weak = elements;
+        if let Some(spec) = caps.get(3) {
+            for sub in spec.as_str().split(';').map(str::trim) {
+                let (list, elements) = if let Some(elements) = sub.strip_prefix("weak to ") {
+                    (&mut weak, elements)
+                } else if let Some(elements) = sub.strip_prefix("immune to ") {
+                    (&mut immune, elements)
                 } else {
-                    immune = elements;
+                    return Err(format!("line {}: invalid modifier {:?}", line, sub))?;
+                };
+                for e in elements.split(',').map(str::trim) {
+                    if e.is_empty() || e.contains(' ') {
+                        return Err(format!("line {}: invalid attack type {:?}", line, e))?;
+                    }
+                    list.push(e.to_string());
                 }
             }
         }
 
-        Army {
-            infection,
-            size,
-            hit_points,
+        Ok(Army {
+            team,
+            group,
+            size: number(1)?,
+            hit_points: number(2)?,
             immune,
             weak,
-            attack,
-            attack_type,
-            initiative,
-        }
-    }
-
-    fn parse_army(s: &str, is_infection: bool) -> Vec<Army> {
-        s.split('\n')
-            .filter(|l| !l.is_empty())
-            .skip(1)
-            .map(|l| Army::parse(l, is_infection))
-            .collect()
+            attack: number(4)?,
+            attack_type: caps[5].to_string(),
+            initiative: number(6)?,
+        })
     }
 
     fn effective_power(&self) -> u64 {
-        self.size * self.attack
+        self.size.saturating_mul(self.attack)
     }
 
     fn damage_to(&self, other: &Army) -> u64 {
@@ -96,85 +90,385 @@ impl Army {
         }
 
         if other.weak.contains(&self.attack_type) {
-            return 2 * self.effective_power();
+            return self.effective_power().saturating_mul(2);
         }
 
         self.effective_power()
     }
 
-    fn deal_damage(&self, other: &mut Army) {
-        if self.size > 0 {
-            let damage = self.damage_to(&other);
-            let unit_killed: u64 = damage / other.hit_points;
-            // println!(
-            //     "{:?} deals {} damage (kill {:?} units) to {:?}",
-            //     self, damage, unit_killed, other
-            // );
-            other.size = other.size.saturating_sub(unit_killed);
-        }
+    // return the damage dealt and the number of units killed
+    fn deal_damage(&self, other: &mut Army) -> (u64, u64) {
+        let damage = self.damage_to(&other);
+        let unit_killed: u64 = (damage / other.hit_points).min(other.size);
+        other.size -= unit_killed;
+        (damage, unit_killed)
     }
 
     fn boost_damage(&mut self, boost: u64) {
-        self.attack += boost;
+        self.attack = self.attack.saturating_add(boost);
     }
 }
 
-fn parse_armies(s: &str) -> Vec<Army> {
-    let mut teams = s.split("\n\n");
-    let mut armies = Army::parse_army(teams.next().unwrap(), false);
-    armies.extend(Army::parse_army(teams.next().unwrap(), true).into_iter());
+// the teams, named after their section header, and all their groups
+#[derive(Debug, Clone)]
+struct Battle {
+    teams: Vec<String>,
+    armies: Vec<Army>,
+}
+
+impl FromStr for Battle {
+    type Err = AocError;
 
-    armies
+    fn from_str(s: &str) -> Result<Battle, AocError> {
+        let mut teams: Vec<String> = vec![];
+        let mut armies = vec![];
+        let mut group = 0;
+
+        for (i, l) in s.lines().enumerate() {
+            let l = l.trim();
+            if l.is_empty() {
+                continue;
+            }
+
+            if l.ends_with(':') {
+                let name = l.trim_end_matches(':').trim();
+                if teams.iter().any(|t| t == name) {
+                    return Err(format!("line {}: team {:?} is defined twice", i + 1, name))?;
+                }
+                teams.push(name.to_string());
+                group = 0;
+            } else if teams.is_empty() {
+                return Err(format!("line {}: group before any team header", i + 1))?;
+            } else {
+                group += 1;
+                armies.push(Army::parse(l, i + 1, teams.len() - 1, group)?);
+            }
+        }
+
+        Ok(Battle { teams, armies })
+    }
 }
 
-fn combat_to_death(armies: &mut Vec<Army>) -> (u64, u64) {
-    let mut previous_state = None;
-    loop {
-        combat_turn(armies);
+impl Battle {
+    fn team(&self, name: &str) -> Result<usize, AocError> {
+        match self.teams.iter().position(|t| t == name) {
+            Some(team) => Ok(team),
+            None => Err(format!("no team named {:?}", name))?,
+        }
+    }
+
+    // fight with the attack of all groups of a team raised by `boost`
+    fn outcome(&self, team: usize, boost: u64) -> Outcome {
+        let mut armies = self.armies.clone();
+        boost_team(&mut armies, team, boost);
+        combat_to_death(&mut armies)
+    }
+
+    // smallest boost for which the team wins, along with the outcome of that battle, or None if
+    // it cannot win. A winning boost is found by doubling it, then bisecting, which assumes that
+    // apart from stalemates a bigger boost never turns a win into a defeat. That is not proven:
+    // a boost also changes the order in which groups choose their targets, and so the targets
+    // themselves. It holds for the example and the puzzle input, see test_minimum_boost_linear.
+    // Stalemates do not follow that order and may sit below a win, so the boosts under the
+    // bisected one are checked down to the first defeat.
+    // The doubling stops at a boost where a single unit of the team wipes out any enemy group in
+    // one hit: a team still not winning there, e.g. because its enemies are immune to its
+    // attacks, is not going to win with more.
+    fn minimum_boost(&self, team: usize) -> Option<(u64, Outcome)> {
+        let mut low = 0; // highest boost known to lose
+        let outcome = self.outcome(team, 0);
+        if outcome.winner() == Some(team) {
+            return Some((0, outcome));
+        }
+
+        let limit = self
+            .armies
+            .iter()
+            .filter(|a| a.team != team)
+            .map(|a| a.size.saturating_mul(a.hit_points))
+            .max()
+            .unwrap_or(0)
+            .max(1);
+        let mut high = 1;
+        let mut best = loop {
+            let outcome = self.outcome(team, high);
+            if outcome.winner() == Some(team) {
+                break outcome;
+            }
+            if high >= limit {
+                return None;
+            }
+            low = high;
+            high = high.saturating_mul(2).min(limit);
+        };
+
+        while high - low > 1 {
+            let mid = low + (high - low) / 2;
+            let outcome = self.outcome(team, mid);
+            if outcome.winner() == Some(team) {
+                high = mid;
+                best = outcome;
+            } else {
+                low = mid;
+            }
+        }
+
+        // a stalemate at `low` may hide a win below it
+        let mut boost = low;
+        loop {
+            let outcome = self.outcome(team, boost);
+            match outcome.winner() {
+                Some(winner) if winner == team => {
+                    high = boost;
+                    best = outcome;
+                }
+                Some(_) => break,
+                None => (),
+            }
+            if boost == 0 {
+                break;
+            }
+            boost -= 1;
+        }
+
+        Some((high, best))
+    }
+
+    fn boost_table(&self, team: usize, boosts: Range<u64>) -> String {
+        boosts
+            .map(|boost| {
+                format!(
+                    "{:>6}: {}\n",
+                    boost,
+                    self.outcome(team, boost).describe(&self.teams)
+                )
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+enum Event {
+    // size of a group at the start of a round
+    Group {
+        team: usize,
+        group: usize,
+        size: u64,
+    },
+    // damage a group would deal to a possible target during target selection
+    WouldDeal {
+        team: usize,
+        group: usize,
+        target: usize,
+        damage: u64,
+    },
+    Attack {
+        team: usize,
+        group: usize,
+        target: usize,
+        damage: u64,
+        killed: u64,
+    },
+    RoundEnd,
+}
+
+impl Event {
+    fn describe(&self, teams: &[String]) -> String {
+        match self {
+            Event::Group { group, size, .. } => format!("Group {} contains {} units", group, size),
+            Event::WouldDeal {
+                team,
+                group,
+                target,
+                damage,
+            } => format!(
+                "{} group {} would deal defending group {} {} damage",
+                teams[*team], group, target, damage
+            ),
+            Event::Attack {
+                team,
+                group,
+                target,
+                killed,
+                ..
+            } => format!(
+                "{} group {} attacks defending group {}, killing {} units",
+                teams[*team], group, target, killed
+            ),
+            Event::RoundEnd => String::new(),
+        }
+    }
+}
+
+// the log of a battle, as the puzzle statement displays it
+fn format_log(teams: &[String], events: &[Event]) -> String {
+    let mut out = String::new();
+    for round in events.split(|e| *e == Event::RoundEnd) {
+        if round.is_empty() {
+            continue;
+        }
+        for (team, name) in teams.iter().enumerate() {
+            out += &format!("{}:\n", name);
+            let groups: Vec<&Event> = round
+                .iter()
+                .filter(|e| matches!(e, Event::Group { team: t, .. } if *t == team))
+                .collect();
+            if groups.is_empty() {
+                out += "No groups remain.\n";
+            }
+            for g in groups {
+                out += &format!("{}\n", g.describe(teams));
+            }
+        }
+        out += "\n";
+        for e in round
+            .iter()
+            .filter(|e| matches!(e, Event::WouldDeal { .. }))
+        {
+            out += &format!("{}\n", e.describe(teams));
+        }
+        out += "\n";
+        for e in round.iter().filter(|e| matches!(e, Event::Attack { .. })) {
+            out += &format!("{}\n", e.describe(teams));
+        }
+        out += "\n";
+    }
+    out
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Outcome {
+    // the only team left, and its remaining units
+    Win { team: usize, units: u64 },
+    // units left to each team still standing, when none of them can kill a unit anymore
+    Stalemate(Vec<(usize, u64)>),
+}
 
-        let score_s: u64 = armies.iter().filter(|a| !a.infection).map(|a| a.size).sum();
-        let score_i: u64 = armies.iter().filter(|a| a.infection).map(|a| a.size).sum();
+impl Outcome {
+    fn of(armies: &[Army]) -> Outcome {
+        let mut units: Vec<(usize, u64)> = vec![];
+        for a in armies {
+            match units.iter_mut().find(|(team, _)| *team == a.team) {
+                Some((_, size)) => *size += a.size,
+                None => units.push((a.team, a.size)),
+            }
+        }
+        units.sort();
 
-        let current_state = (score_s, score_i);
+        match units[..] {
+            [(team, units)] => Outcome::Win { team, units },
+            _ => Outcome::Stalemate(units),
+        }
+    }
 
-        if Some(current_state) == previous_state || score_s == 0 || score_i == 0 {
-            return (score_s, score_i); //break if dead lock, or if there is a winner
+    fn winner(&self) -> Option<usize> {
+        match self {
+            Outcome::Win { team, .. } => Some(*team),
+            Outcome::Stalemate(_) => None,
         }
+    }
 
-        previous_state = Some(current_state);
+    fn describe(&self, teams: &[String]) -> String {
+        match self {
+            Outcome::Win { team, units } => format!("{} wins with {} units", teams[*team], units),
+            Outcome::Stalemate(units) => format!(
+                "stalemate with {}",
+                units
+                    .iter()
+                    .map(|(team, units)| format!("{} {} units", units, teams[*team]))
+                    .collect::<Vec<_>>()
+                    .join(" and ")
+            ),
+        }
     }
 }
 
+fn combat_to_death(armies: &mut Vec<Army>) -> Outcome {
+    combat_to_death_logged(armies, &mut vec![])
+}
+
+fn combat_to_death_logged(armies: &mut Vec<Army>, log: &mut Vec<Event>) -> Outcome {
+    loop {
+        let killed = combat_turn_logged(armies, log);
+
+        // a turn without any kill leaves the armies unchanged, so the next ones will too
+        if killed == 0 || armies.iter().all(|a| a.team == armies[0].team) {
+            return Outcome::of(armies);
+        }
+    }
+}
+
+#[cfg(test)]
 fn combat_target(armies: &[Army]) -> Vec<Option<usize>> {
+    combat_target_logged(armies, &mut vec![])
+}
+
+fn combat_target_logged(armies: &[Army], log: &mut Vec<Event>) -> Vec<Option<usize>> {
     let mut attack_targets = vec![None; armies.len()];
 
     let mut orders: Vec<usize> = (0..armies.len()).collect();
     orders.sort_by_key(|&idx| {
         let a = &armies[idx];
-        (-(a.effective_power() as i64), -(a.initiative as i64))
+        (
+            cmp::Reverse(a.effective_power()),
+            cmp::Reverse(a.initiative),
+        )
     });
 
+    let mut would_deal = vec![];
     for o in orders {
+        for (idx, a) in armies.iter().enumerate() {
+            let damage = armies[o].damage_to(a);
+            if a.team != armies[o].team && damage > 0 && !attack_targets.contains(&Some(idx)) {
+                would_deal.push(Event::WouldDeal {
+                    team: armies[o].team,
+                    group: armies[o].group,
+                    target: a.group,
+                    damage,
+                });
+            }
+        }
+
         let candidate_idx = armies
             .iter()
             .enumerate()
             // don't attach the same side
-            .filter(|(idx, _)| armies[o].infection != armies[*idx].infection)
+            .filter(|(idx, _)| armies[o].team != armies[*idx].team)
             // don't attack the same target twice
             .filter(|(idx, _)| !attack_targets.contains(&Some(*idx)))
             .filter(|(_, a)| armies[o].damage_to(&a) > 0)
             .max_by_key(|(_, a)| (armies[o].damage_to(&a), a.effective_power(), a.initiative))
             .map(|(idx, _)| idx);
 
-        //println!("{:?} will target {:?}", armies[o], candidate_idx);
         attack_targets[o] = candidate_idx;
     }
 
+    // the puzzle lists the possible targets by attacking group, the last team first
+    would_deal.sort_by_key(|e| match *e {
+        Event::WouldDeal { team, group, .. } => (cmp::Reverse(team), group),
+        _ => unreachable!(),
+    });
+    log.extend(would_deal);
+
     attack_targets
 }
 
-fn combat_turn(armies: &mut Vec<Army>) {
-    let attack_targets = combat_target(armies);
+#[cfg(test)]
+fn combat_turn(armies: &mut Vec<Army>) -> u64 {
+    combat_turn_logged(armies, &mut vec![])
+}
+
+// return the number of units killed during the turn
+fn combat_turn_logged(armies: &mut Vec<Army>, log: &mut Vec<Event>) -> u64 {
+    for a in armies.iter() {
+        log.push(Event::Group {
+            team: a.team,
+            group: a.group,
+            size: a.size,
+        });
+    }
+
+    let attack_targets = combat_target_logged(armies, log);
 
     let mut attacks: Vec<(usize, usize, u64)> = attack_targets
         .iter()
@@ -184,75 +478,168 @@ fn combat_turn(armies: &mut Vec<Army>) {
         .collect();
 
     // combat order
-    attacks.sort_by_key(|&(_at, _def, ini)| -(ini as i64));
+    attacks.sort_by_key(|&(_at, _def, ini)| cmp::Reverse(ini));
+    let mut total_killed = 0;
     attacks.iter_mut().for_each(|&mut (at, def, _)| {
         let at = armies[at].clone(); // seems dirty...
         let def = &mut armies[def];
-        at.deal_damage(def);
+        if at.size > 0 {
+            let (damage, killed) = at.deal_damage(def);
+            log.push(Event::Attack {
+                team: at.team,
+                group: at.group,
+                target: def.group,
+                damage,
+                killed,
+            });
+            total_killed += killed;
+        }
     });
 
     armies.retain(|a| a.size > 0);
+    log.push(Event::RoundEnd);
+    total_killed
 }
 
-fn boost_immune_system(armies: &mut Vec<Army>, boost: u64) {
+fn boost_team(armies: &mut [Army], team: usize, boost: u64) {
     armies
         .iter_mut()
-        .filter(|a| !a.infection)
+        .filter(|a| a.team == team)
         .for_each(|a| a.boost_damage(boost));
 }
 
-pub fn answer1() -> Result<AocResponse<u64>, AocError> {
-    let s = std::fs::read_to_string("input/input24.txt")?;
-    let mut armies = parse_armies(&s);
+// print the outcome of the battle for each boost of the immune system in the range
+pub fn print_boost_table(filename: &str, boosts: Range<u64>) -> Result<(), AocError> {
+    let s = std::fs::read_to_string(filename)?;
+    let battle = Battle::from_str(&s)?;
+
+    print!(
+        "{}",
+        battle.boost_table(battle.team(IMMUNE_SYSTEM)?, boosts)
+    );
+    Ok(())
+}
 
-    let (score_s, score_i) = combat_to_death(&mut armies);
+// print the battle log round by round, as in the puzzle statement
+pub fn replay(filename: &str, boost: u64, delay_ms: u64) -> Result<(), AocError> {
+    let s = std::fs::read_to_string(filename)?;
+    let battle = Battle::from_str(&s)?;
+    let mut armies = battle.armies.clone();
+    boost_team(&mut armies, battle.team(IMMUNE_SYSTEM)?, boost);
+
+    let mut log = vec![];
+    combat_to_death_logged(&mut armies, &mut log);
+    for round in log.split_inclusive(|e| *e == Event::RoundEnd) {
+        print!("{}", format_log(&battle.teams, round));
+        thread::sleep(Duration::from_millis(delay_ms));
+    }
 
-    Ok(AocResponse::new(
-        24,
-        1,
-        "Immune System Simulator 20XX",
-        score_s.max(score_i),
-    ))
+    Ok(())
+}
+
+pub fn answer1() -> Result<AocResponse<u64>, AocError> {
+    let s = std::fs::read_to_string("input/input24.txt")?;
+    let battle = Battle::from_str(&s)?;
+
+    match battle.outcome(0, 0) {
+        Outcome::Win { units, .. } => Ok(AocResponse::new(
+            24,
+            1,
+            "Immune System Simulator 20XX",
+            units,
+        )),
+        Outcome::Stalemate(_) => Err(AocError::ComputeNotFound),
+    }
 }
 
 pub fn answer2() -> Result<AocResponse<u64>, AocError> {
     let s = std::fs::read_to_string("input/input24.txt")?;
-    let armies = parse_armies(&s);
-
-    for boost in 0.. {
-        let mut boostest_armies = armies.clone();
-        //println!("trying boost {}", boost);
-        boost_immune_system(&mut boostest_armies, boost);
-
-        let (score_s, score_i) = combat_to_death(&mut boostest_armies);
-        if score_i == 0 {
-            return Ok(AocResponse::new(
-                24,
-                2,
-                "Immune System Simulator 20XX",
-                score_s,
-            ));
-        }
+    let battle = Battle::from_str(&s)?;
+
+    match battle.minimum_boost(battle.team(IMMUNE_SYSTEM)?) {
+        Some((_, Outcome::Win { units, .. })) => Ok(AocResponse::new(
+            24,
+            2,
+            "Immune System Simulator 20XX",
+            units,
+        )),
+        _ => Err(AocError::ComputeNotFound),
     }
-    unreachable!()
 }
 
 #[test]
 fn test_parse() {
     let s = std::fs::read_to_string("input/input24_debug.txt").expect("cannot read file");
 
-    let armies = parse_armies(&s);
-    println!("armies: {:?}", armies);
+    let battle = Battle::from_str(&s).unwrap();
+    println!("armies: {:?}", battle.armies);
 
-    assert_eq!(4, armies.len());
+    assert_eq!(vec!["Immune System", "Infection"], battle.teams);
+    assert_eq!(4, battle.armies.len());
+}
+
+#[test]
+fn test_parse_teams() {
+    let battle = Battle::from_str(
+        r#"Elves:
+1 unit each with 10 hit points with an attack that does 3 frost damage at initiative 1
+2 units each with 20 hit points (immune to acid, fire; weak to frost) with an attack that does 4 fire damage at initiative 2
+
+Goblins:
+3 units each with 30 hit points (weak to fire) with an attack that does 5 acid damage at initiative 3
+
+Trolls:
+"#,
+    )
+    .unwrap();
+
+    assert_eq!(vec!["Elves", "Goblins", "Trolls"], battle.teams);
+    assert_eq!(3, battle.armies.len());
+    let a = &battle.armies[1];
+    assert_eq!((0, 2), (a.team, a.group));
+    assert_eq!(vec!["acid", "fire"], a.immune);
+    assert_eq!(vec!["frost"], a.weak);
+    assert_eq!("fire", a.attack_type);
+    assert_eq!((1, 1), (battle.armies[2].team, battle.armies[2].group));
+
+    let err = |s: &str| match Battle::from_str(s) {
+        Err(AocError::InvalidToken(msg)) => msg,
+        other => panic!("unexpected {:?}", other),
+    };
+    assert!(err("Elves:\n\n1 unit each with 10 hit points at initiative 1").starts_with("line 3:"));
+    assert!(err(
+        "1 unit each with 10 hit points with an attack that does 3 frost damage at initiative 1"
+    )
+    .starts_with("line 1:"));
+    assert!(err("Elves:\n1 unit each with 10 hit points (strong to fire) with an attack that does 3 frost damage at initiative 1").starts_with("line 2: invalid modifier"));
+    assert!(err("Elves:\nElves:").starts_with("line 2:"));
+}
+
+#[cfg(test)]
+const THREE_TEAMS: &str = r#"Elves:
+10 units each with 10 hit points with an attack that does 10 fire damage at initiative 3
+
+Goblins:
+10 units each with 10 hit points with an attack that does 1 fire damage at initiative 2
+
+Trolls:
+10 units each with 10 hit points with an attack that does 1 fire damage at initiative 1
+"#;
+
+#[test]
+fn test_three_teams() {
+    let battle = Battle::from_str(THREE_TEAMS).unwrap();
+
+    // the goblins and the trolls both target the elves, who wipe out one team per round first
+    assert_eq!(Outcome::Win { team: 0, units: 10 }, battle.outcome(0, 0));
 }
 
 #[test]
 fn test_targeting() {
     let s = std::fs::read_to_string("input/input24_debug.txt").expect("cannot read file");
-    let mut armies = parse_armies(&s);
+    let armies = Battle::from_str(&s).unwrap().armies;
 
-    let targets = combat_target(&mut armies);
+    let targets = combat_target(&armies);
     println!("targets: {:?}", targets);
 
     assert_eq!(Some(3), targets[0]);
@@ -261,10 +648,27 @@ fn test_targeting() {
     assert_eq!(Some(1), targets[3]);
 }
 
+#[test]
+fn test_targeting_large_numbers() {
+    let battle = Battle::from_str(
+        r#"Elves:
+1 unit each with 10 hit points with an attack that does 10 fire damage at initiative 18446744073709551615
+4294967296 units each with 10 hit points with an attack that does 4294967296 fire damage at initiative 1
+
+Goblins:
+1 unit each with 10 hit points with an attack that does 1 fire damage at initiative 2
+"#,
+    )
+    .unwrap();
+
+    // the group with the most effective power chooses first, even beyond what an i64 holds
+    assert_eq!(vec![None, Some(2), Some(1)], combat_target(&battle.armies));
+}
+
 #[test]
 fn test_combat_turn() {
     let s = std::fs::read_to_string("input/input24_debug.txt").expect("cannot read file");
-    let mut armies = parse_armies(&s);
+    let mut armies = Battle::from_str(&s).unwrap().armies;
 
     combat_turn(&mut armies);
 
@@ -274,8 +678,126 @@ fn test_combat_turn() {
 #[test]
 fn test_combat_to_death() {
     let s = std::fs::read_to_string("input/input24_debug.txt").expect("cannot read file");
-    let mut armies = parse_armies(&s);
-
-    assert_eq!((0, 782 + 4434), combat_to_death(&mut armies));
+    let mut armies = Battle::from_str(&s).unwrap().armies;
+
+    assert_eq!(
+        Outcome::Win {
+            team: 1,
+            units: 782 + 4434
+        },
+        combat_to_death(&mut armies)
+    );
     assert_eq!(2, armies.len());
 }
+
+#[test]
+fn test_battle_log() {
+    let s = std::fs::read_to_string("input/input24_debug.txt").expect("cannot read file");
+    let battle = Battle::from_str(&s).unwrap();
+    let mut armies = battle.armies.clone();
+
+    let mut log = vec![];
+    combat_turn_logged(&mut armies, &mut log);
+
+    assert_eq!(
+        r#"Immune System:
+Group 1 contains 17 units
+Group 2 contains 989 units
+Infection:
+Group 1 contains 801 units
+Group 2 contains 4485 units
+
+Infection group 1 would deal defending group 1 185832 damage
+Infection group 1 would deal defending group 2 185832 damage
+Infection group 2 would deal defending group 2 107640 damage
+Immune System group 1 would deal defending group 1 76619 damage
+Immune System group 1 would deal defending group 2 153238 damage
+Immune System group 2 would deal defending group 1 24725 damage
+
+Infection group 2 attacks defending group 2, killing 84 units
+Immune System group 2 attacks defending group 1, killing 4 units
+Immune System group 1 attacks defending group 2, killing 51 units
+Infection group 1 attacks defending group 1, killing 17 units
+
+"#,
+        format_log(&battle.teams, &log)
+    );
+
+    combat_to_death_logged(&mut armies, &mut log);
+    let last_attack = log.iter().rev().find(|e| matches!(e, Event::Attack { .. }));
+    assert_eq!(
+        "Infection group 1 attacks defending group 2, killing 49 units",
+        last_attack.unwrap().describe(&battle.teams)
+    );
+    assert_eq!(
+        vec![(1, 782), (2, 4434)],
+        armies.iter().map(|a| (a.group, a.size)).collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn test_outcomes() {
+    let s = std::fs::read_to_string("input/input24_debug.txt").expect("cannot read file");
+    let battle = Battle::from_str(&s).unwrap();
+
+    assert_eq!(
+        Outcome::Win {
+            team: 1,
+            units: 782 + 4434
+        },
+        battle.outcome(0, 0)
+    );
+    assert_eq!(Outcome::Win { team: 0, units: 51 }, battle.outcome(0, 1570));
+    assert_eq!(
+        Some((1570, Outcome::Win { team: 0, units: 51 })),
+        battle.minimum_boost(0)
+    );
+
+    assert_eq!(
+        "  1569: Infection wins with 139 units\n  1570: Immune System wins with 51 units\n",
+        battle.boost_table(0, 1569..1571)
+    );
+}
+
+#[test]
+fn test_stalemate() {
+    let battle = Battle::from_str(
+        r#"Immune System:
+10 units each with 100 hit points with an attack that does 5 fire damage at initiative 2
+
+Infection:
+10 units each with 100 hit points (immune to fire) with an attack that does 5 cold damage at initiative 1"#,
+    )
+    .unwrap();
+
+    let outcome = battle.outcome(0, 0);
+    assert_eq!(Outcome::Stalemate(vec![(0, 10), (1, 10)]), outcome);
+    assert_eq!(
+        "stalemate with 10 Immune System units and 10 Infection units",
+        outcome.describe(&battle.teams)
+    );
+
+    // no boost helps the immune system against an infection immune to fire
+    assert_eq!(None, battle.minimum_boost(0));
+}
+
+#[test]
+fn test_minimum_boost_linear() {
+    let example = std::fs::read_to_string("input/input24_debug.txt").expect("cannot read file");
+    let input = std::fs::read_to_string("input/input24.txt").expect("cannot read file");
+    let battles = [
+        (example.as_str(), 0),
+        (input.as_str(), 0),
+        (THREE_TEAMS, 1),
+        (THREE_TEAMS, 2),
+    ];
+
+    // the bisection must agree with trying every boost
+    for (s, team) in battles.iter() {
+        let battle = Battle::from_str(s).unwrap();
+        let linear = (0..5000)
+            .map(|boost| (boost, battle.outcome(*team, boost)))
+            .find(|(_, outcome)| outcome.winner() == Some(*team));
+        assert_eq!(linear, battle.minimum_boost(*team));
+    }
+}
@@ -3,11 +3,14 @@ use crate::common::response::AocResponse;
 
 use lazy_static::lazy_static;
 use regex::Regex;
+use std::collections::HashMap;
 use std::fs::File;
 use std::io::{BufRead, BufReader};
+use std::iter::FromIterator;
 use std::str::FromStr;
 
-struct Point(i64, i64, i64, i64);
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Point(pub i64, pub i64, pub i64, pub i64);
 
 impl FromStr for Point {
     type Err = AocError;
@@ -38,27 +41,156 @@ impl Point {
     }
 }
 
-fn neighbours(points: &[Point]) -> Vec<Vec<usize>> {
-    let mut neighbours = vec![vec![]];
-    for p1 in points {
-        let ns: Vec<usize> = points
-            .into_iter()
-            .enumerate()
-            .filter(|(_, p2)| p1.distance_to(&p2) <= 3)
-            .map(|(ix, _)| ix)
+// points close enough are in the same constellation
+const LINK_DISTANCE: i64 = 3;
+
+type Cell = (i64, i64, i64, i64);
+
+// incremental clustering of the points in constellations: a union-find over the points, with the
+// points bucketed in a grid of side LINK_DISTANCE so that only the 3^4 cells around a new point
+// need to be searched for neighbours
+#[derive(Default)]
+pub struct Constellations {
+    points: Vec<Point>,
+    parent: Vec<usize>,
+    size: Vec<usize>,
+    grid: HashMap<Cell, Vec<usize>>,
+    count: usize,
+}
+
+impl Constellations {
+    pub fn new() -> Self {
+        Constellations::default()
+    }
+
+    fn cell(p: &Point) -> Cell {
+        (
+            p.0.div_euclid(LINK_DISTANCE),
+            p.1.div_euclid(LINK_DISTANCE),
+            p.2.div_euclid(LINK_DISTANCE),
+            p.3.div_euclid(LINK_DISTANCE),
+        )
+    }
+
+    // add a point, merging all the constellations it links together, and return its index
+    pub fn add(&mut self, point: Point) -> usize {
+        let ix = self.points.len();
+        let cell = Self::cell(&point);
+
+        let mut close = vec![];
+        for d0 in -1..=1 {
+            for d1 in -1..=1 {
+                for d2 in -1..=1 {
+                    for d3 in -1..=1 {
+                        let around = (cell.0 + d0, cell.1 + d1, cell.2 + d2, cell.3 + d3);
+                        if let Some(ixs) = self.grid.get(&around) {
+                            close.extend(ixs.iter().filter(|&&other| {
+                                self.points[other].distance_to(&point) <= LINK_DISTANCE as u64
+                            }));
+                        }
+                    }
+                }
+            }
+        }
+
+        self.points.push(point);
+        self.parent.push(ix);
+        self.size.push(1);
+        self.grid.entry(cell).or_default().push(ix);
+        self.count += 1;
+
+        for other in close {
+            self.union(ix, other);
+        }
+
+        ix
+    }
+
+    // union by size keeps the trees shallow, so no need for path compression (and &mut self)
+    fn find(&self, mut ix: usize) -> usize {
+        while self.parent[ix] != ix {
+            ix = self.parent[ix];
+        }
+        ix
+    }
+
+    fn union(&mut self, a: usize, b: usize) {
+        let (a, b) = (self.find(a), self.find(b));
+        if a == b {
+            return;
+        }
+        let (big, small) = if self.size[a] >= self.size[b] {
+            (a, b)
+        } else {
+            (b, a)
+        };
+        self.parent[small] = big;
+        self.size[big] += self.size[small];
+        self.count -= 1;
+    }
+
+    pub fn point(&self, ix: usize) -> &Point {
+        &self.points[ix]
+    }
+
+    // number of constellations
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    // an identifier of the constellation of a point, shared by all the points of the constellation
+    // (it may change when adding new points)
+    pub fn constellation_of(&self, ix: usize) -> usize {
+        self.find(ix)
+    }
+
+    pub fn same_constellation(&self, a: usize, b: usize) -> bool {
+        self.find(a) == self.find(b)
+    }
+
+    pub fn size_of(&self, ix: usize) -> usize {
+        self.size[self.find(ix)]
+    }
+
+    // the sizes of all the constellations, largest first
+    pub fn sizes(&self) -> Vec<usize> {
+        let mut sizes: Vec<usize> = (0..self.points.len())
+            .filter(|&ix| self.parent[ix] == ix)
+            .map(|ix| self.size[ix])
             .collect();
-        neighbours.push(ns);
+        sizes.sort_by(|a, b| b.cmp(a));
+        sizes
+    }
+
+    // the indexes of the points of each constellation
+    pub fn members(&self) -> Vec<Vec<usize>> {
+        let mut members: HashMap<usize, Vec<usize>> = HashMap::new();
+        for ix in 0..self.points.len() {
+            members.entry(self.find(ix)).or_default().push(ix);
+        }
+        let mut members: Vec<Vec<usize>> = members.into_values().collect();
+        members.sort();
+        members
     }
+}
 
-    neighbours
+impl FromIterator<Point> for Constellations {
+    fn from_iter<I: IntoIterator<Item = Point>>(points: I) -> Self {
+        let mut constellations = Constellations::new();
+        for p in points {
+            constellations.add(p);
+        }
+        constellations
+    }
 }
 
 pub fn answer1() -> Result<AocResponse<usize>, AocError> {
     let points = read_file("input/input25.txt")?;
-    let neighbours = neighbours(&points);
-
-    // https://docs.rs/pathfinding/1.1.10/pathfinding/undirected/connected_components/fn.components.html
-    let constellations = pathfinding::undirected::connected_components::components(&neighbours);
+    let constellations: Constellations = points.into_iter().collect();
 
     Ok(AocResponse::new(
         25,
@@ -85,8 +217,39 @@ fn read_file(filename: &str) -> Result<Vec<Point>, AocError> {
 #[test]
 fn test_constellations() {
     let points = read_file("input/input25_debug.txt").unwrap();
-    let neighbours = neighbours(&points);
-    let constellations = pathfinding::undirected::connected_components::components(&neighbours);
+    let constellations: Constellations = points.into_iter().collect();
 
     assert_eq!(4, constellations.len());
 }
+
+#[test]
+fn test_constellations_incremental() {
+    let mut constellations = Constellations::new();
+    let a = constellations.add(Point(0, 0, 0, 0));
+    let b = constellations.add(Point(6, 0, 0, 0));
+    assert_eq!(2, constellations.len());
+    assert!(!constellations.same_constellation(a, b));
+
+    // bridges the two points
+    let c = constellations.add(Point(3, 0, 0, 0));
+    assert_eq!(1, constellations.len());
+    assert!(constellations.same_constellation(a, b));
+    assert_eq!(3, constellations.size_of(c));
+
+    constellations.add(Point(-1, -1, -1, 0));
+    constellations.add(Point(0, 0, 10, 0));
+    assert_eq!(vec![4, 1], constellations.sizes());
+    assert_eq!(vec![vec![0, 1, 2, 3], vec![4]], constellations.members());
+}
+
+#[test]
+fn test_constellations_scale() {
+    // a chain of points each linked to the previous one, and as many isolated points
+    let n = 25_000;
+    let chain = (0..n).map(|i| Point(i * 3, 0, 0, 0));
+    let isolated = (0..n).map(|i| Point(i * 4, 100, -i * 4, i % 7));
+    let constellations: Constellations = chain.chain(isolated).collect();
+
+    assert_eq!(n as usize + 1, constellations.len());
+    assert_eq!(n as usize, constellations.sizes()[0]);
+}